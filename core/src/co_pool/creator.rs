@@ -1,8 +1,8 @@
 use crate::co_pool::CoroutinePool;
-use crate::common::constants::CoroutineState;
+use crate::common::constants::{CoroutineState, TaskStatus};
 use crate::coroutine::listener::Listener;
 use crate::coroutine::local::CoroutineLocal;
-use crate::scheduler::SchedulableCoroutineState;
+use crate::scheduler::{SchedulableCoroutine, SchedulableCoroutineState};
 use std::sync::atomic::Ordering;
 
 #[repr(C)]
@@ -12,10 +12,22 @@ pub(crate) struct CoroutineCreator {}
 impl Listener<(), Option<usize>> for CoroutineCreator {
     fn on_state_changed(
         &self,
-        _: &CoroutineLocal,
+        local: &CoroutineLocal,
         _: SchedulableCoroutineState,
         new_state: SchedulableCoroutineState,
     ) {
+        match new_state {
+            CoroutineState::Running => {
+                CoroutinePool::running_task_changed(local, TaskStatus::Running);
+            }
+            CoroutineState::Suspend((), _) => {
+                CoroutinePool::running_task_changed(local, TaskStatus::Suspended);
+            }
+            CoroutineState::Syscall((), syscall, _) => {
+                CoroutinePool::running_task_changed(local, TaskStatus::InSyscall(syscall));
+            }
+            _ => {}
+        }
         match new_state {
             CoroutineState::Suspend((), _) | CoroutineState::Syscall((), _, _) => {
                 if let Some(pool) = CoroutinePool::current() {
@@ -29,11 +41,17 @@ impl Listener<(), Option<usize>> for CoroutineCreator {
                         .store(pool.get_running_size().saturating_sub(1), Ordering::Release);
                 }
             }
-            CoroutineState::Error(_) => {
+            CoroutineState::Error(_) | CoroutineState::Cancelled => {
                 if let Some(pool) = CoroutinePool::current() {
                     //worker协程异常退出，需要先回收再创建
                     pool.running
                         .store(pool.get_running_size().saturating_sub(1), Ordering::Release);
+                    //正在执行的任务随worker协程一起失败
+                    if let Some(co) = SchedulableCoroutine::current() {
+                        if let Some(error) = co.take_error() {
+                            pool.fail_running_task(co.name(), error);
+                        }
+                    }
                     _ = pool.try_grow();
                 }
             }
@@ -1,19 +1,29 @@
 use crate::co_pool::creator::CoroutineCreator;
-use crate::co_pool::task::Task;
+use crate::co_pool::task::{Period, Periodic, Task};
 use crate::common::beans::BeanFactory;
-use crate::common::constants::PoolState;
-use crate::common::ordered_work_steal::{OrderedLocalQueue, OrderedWorkStealQueue};
+use crate::common::constants::{PoolState, TaskStatus};
+use crate::common::join_error::{JoinError, JoinErrorKind};
+use crate::common::ordered_work_steal::{
+    Ordered, OrderedLocalQueue, OrderedWorkStealQueue, DEFAULT_PRECEDENCE,
+};
+use crate::common::timer_wheel::{TimerKey, TimerWheel};
 use crate::common::{get_timeout_time, now, CondvarBlocker};
+use crate::coroutine::id::CoroutineId;
+use crate::coroutine::local::{CoroutineLocal, InheritableLocals};
 use crate::coroutine::suspender::Suspender;
-use crate::scheduler::{SchedulableCoroutine, Scheduler};
-use crate::{error, impl_current_for, impl_display_by_debug, impl_for_named, trace};
-use dashmap::DashMap;
+use crate::scheduler::{ReadyQueueKind, SchedulableCoroutine, Scheduler, StoredResult, Unparker};
+use crate::{catch, error, impl_current_for, impl_display_by_debug, impl_for_named, trace};
+use dashmap::mapref::entry::Entry;
+use dashmap::{DashMap, DashSet};
+use once_cell::sync::Lazy;
+use std::any::Any;
 use std::cell::Cell;
+use std::collections::BTreeMap;
 use std::ffi::c_longlong;
 use std::io::{Error, ErrorKind};
 use std::ops::{Deref, DerefMut};
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use std::sync::{Arc, Condvar, Mutex};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
 use std::time::Duration;
 
 /// Task abstraction and impl.
@@ -25,14 +35,131 @@ mod state;
 /// Creator for coroutine pool.
 mod creator;
 
-/// The coroutine pool impls.
+/// The running tasks and the coroutines running them.
+static RUNNING_TASKS: Lazy<DashMap<String, String>> = Lazy::new(DashMap::new);
+
+/// The status of unfinished tasks, a task may be stolen by another pool.
+static TASK_STATUS: Lazy<DashMap<String, TaskStatus>> = Lazy::new(DashMap::new);
+
+/// The tasks in the task queues, with the pools they're submitted to and their priorities.
+static QUEUED_TASKS: Lazy<DashMap<String, (String, c_longlong)>> = Lazy::new(DashMap::new);
+
+/// The coroutine local key of the task running in the worker coroutine.
+const RUNNING_TASK: &str = "open_coroutine_running_task";
+
+/// The names of queued tasks that are asked to cancel.
+static CANCEL_TASKS: Lazy<DashSet<String>> = Lazy::new(DashSet::new);
+
+/// The policies used by [`CoroutinePool`] to pick the next task.
 #[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum TaskPolicy {
+    /// Always pick the task with the highest priority, FIFO within a priority.
+    #[default]
+    Priority,
+    /// Always pick the task with the earliest deadline, the tasks without deadline are picked
+    /// last in FIFO order.
+    ///
+    /// The queued tasks are taken out of the work-stealing queue to be sorted,
+    /// so they can no longer be stolen by other pools.
+    EarliestDeadline,
+}
+
+/// The policies used by [`CoroutinePool`] when a task is submitted to the full task queue,
+/// the same as the rejection policies of Java's `ThreadPoolExecutor`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub enum RejectPolicy {
+    /// Refuse the task with a [`JoinErrorKind::Rejected`] error.
+    #[default]
+    Abort,
+    /// Run the task inline in the submitting coroutine or thread.
+    CallerRuns,
+    /// Drop the task at the head of the queue, its joiners will get
+    /// [`JoinErrorKind::Rejected`], then queue the new task.
+    DiscardOldest,
+    /// Wait until the queue has space, the submitting coroutine is parked so that other
+    /// coroutines can still run, the submitting thread is blocked.
+    Block,
+}
+
+/// A task name claimed in [`TASK_STATUS`], it's released when dropped unless accepted.
 #[derive(Debug)]
+struct ClaimedName(Option<String>);
+
+impl ClaimedName {
+    fn name(&self) -> &str {
+        self.0.as_deref().expect("the name has been accepted")
+    }
+
+    /// The task has been queued, keep the name.
+    fn accept(mut self) -> String {
+        self.0.take().expect("the name has been accepted")
+    }
+}
+
+impl Drop for ClaimedName {
+    fn drop(&mut self) {
+        if let Some(name) = self.0.take() {
+            _ = TASK_STATUS.remove(&name);
+        }
+    }
+}
+
+/// A submitter blocked for the space of the task queue, it's deregistered on unwinding.
+struct SpaceWaiter<'w, 'p>(&'w CoroutinePool<'p>, u64);
+
+impl SpaceWaiter<'_, '_> {
+    /// Returns `true` if it has been woken up since the last registration.
+    fn deregister(&self) -> bool {
+        self.0.space_waiters().remove(&self.1).is_none()
+    }
+
+    fn leave(self, admitted: bool) {
+        if self.deregister() {
+            //消耗掉唤醒许可，避免之后的park立即返回
+            Scheduler::park();
+            if !admitted {
+                self.0.notify_space();
+            }
+        }
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for SpaceWaiter<'_, '_> {
+    fn drop(&mut self) {
+        //被唤醒但没有使用空间，转交给下一个等待者
+        if self.deregister() {
+            self.0.notify_space();
+        }
+    }
+}
+
+/// The coroutine pool impls.
+#[repr(C)]
+#[derive(educe::Educe)]
+#[educe(Debug)]
 pub struct CoroutinePool<'p> {
     //协程池状态
     state: Cell<PoolState>,
     //任务队列
     task_queue: OrderedLocalQueue<'p, Task<'p>>,
+    //选取任务的策略
+    task_policy: TaskPolicy,
+    //任务队列容量
+    task_capacity: AtomicUsize,
+    //任务队列已满时的拒绝策略
+    reject_policy: RejectPolicy,
+    //按登记序号排序的等待任务队列空闲的协程或线程
+    space_waiters: Mutex<BTreeMap<u64, Unparker>>,
+    space_seq: AtomicU64,
+    //按(截止时间, 序号)排序的任务
+    deadline_queue: Mutex<BTreeMap<(u64, u64), Task<'p>>>,
+    deadline_seq: AtomicU64,
+    //延迟任务和周期任务，到期后才放入任务队列
+    timers: Mutex<TimerWheel<Task<'p>>>,
+    timer_keys: DashMap<String, TimerKey>,
     //工作协程组
     workers: Scheduler<'p>,
     //当前协程数
@@ -48,11 +175,22 @@ pub struct CoroutinePool<'p> {
     //阻滞器
     blocker: Arc<CondvarBlocker>,
     //正在等待结果的
-    waits: DashMap<&'p str, Arc<(Mutex<bool>, Condvar)>>,
-    //任务执行结果
-    results: DashMap<String, Result<Option<usize>, &'p str>>,
+    waits: DashMap<String, Arc<(Mutex<bool>, Condvar)>>,
+    //正在执行任务的工作协程
+    running_coroutines: DashSet<String>,
+    //任务执行结果及其完成时间
+    results: DashMap<String, StoredResult>,
+    next_expire: AtomicU64,
+    //任务完成时的回调
+    #[educe(Debug(ignore))]
+    callbacks: DashMap<String, Callback<'p>>,
 }
 
+/// The callback which consumes the result of a task, it's called with the pool which
+/// completed the task.
+type Callback<'c> =
+    Box<dyn FnOnce(&CoroutinePool<'c>, Result<Box<dyn Any + Send>, JoinError>) + 'c>;
+
 impl Drop for CoroutinePool<'_> {
     fn drop(&mut self) {
         if std::thread::panicking() {
@@ -70,7 +208,7 @@ impl Drop for CoroutinePool<'_> {
             self.get_running_size(),
             "There are still tasks in progress !"
         );
-        if !self.task_queue.is_empty() {
+        if !self.is_empty() {
             error!("Forget some tasks when closing the pool");
         }
     }
@@ -118,7 +256,27 @@ impl<'p> CoroutinePool<'p> {
         max_size: usize,
         keep_alive_time: u64,
     ) -> Self {
-        let mut workers = Scheduler::new(name, stack_size);
+        Self::with_ready_queue(
+            name,
+            stack_size,
+            min_size,
+            max_size,
+            keep_alive_time,
+            ReadyQueueKind::default(),
+        )
+    }
+
+    /// Create a new `CoroutinePool` instance whose scheduler uses the `ready_queue`.
+    #[must_use]
+    pub fn with_ready_queue(
+        name: String,
+        stack_size: usize,
+        min_size: usize,
+        max_size: usize,
+        keep_alive_time: u64,
+        ready_queue: ReadyQueueKind,
+    ) -> Self {
+        let mut workers = Scheduler::with_ready_queue(name, stack_size, ready_queue.create());
         workers.add_listener(CoroutineCreator::default());
         CoroutinePool {
             state: Cell::new(PoolState::Running),
@@ -131,9 +289,21 @@ impl<'p> CoroutinePool<'p> {
                 crate::common::constants::TASK_GLOBAL_QUEUE_BEAN,
             )
             .local_queue(),
+            task_policy: TaskPolicy::default(),
+            task_capacity: AtomicUsize::new(usize::MAX),
+            reject_policy: RejectPolicy::default(),
+            space_waiters: Mutex::new(BTreeMap::new()),
+            space_seq: AtomicU64::new(0),
+            deadline_queue: Mutex::new(BTreeMap::new()),
+            deadline_seq: AtomicU64::new(0),
+            timers: Mutex::new(TimerWheel::new(now())),
+            timer_keys: DashMap::new(),
             keep_alive_time: AtomicU64::new(keep_alive_time),
             blocker: Arc::default(),
+            running_coroutines: DashSet::new(),
             results: DashMap::new(),
+            next_expire: AtomicU64::new(0),
+            callbacks: DashMap::new(),
             waits: DashMap::default(),
         }
     }
@@ -183,31 +353,142 @@ impl<'p> CoroutinePool<'p> {
 
     /// Returns the number of tasks owned by this pool.
     pub fn size(&self) -> usize {
-        self.task_queue.len()
+        self.task_queue.len() + self.deadline_queue().len()
+    }
+
+    /// Get the policy used to pick the next task.
+    pub fn task_policy(&self) -> TaskPolicy {
+        self.task_policy
+    }
+
+    /// Set the policy used to pick the next task.
+    pub fn set_task_policy(&mut self, policy: TaskPolicy) {
+        if TaskPolicy::Priority == policy {
+            for (_, task) in std::mem::take(&mut *self.deadline_queue()) {
+                self.task_queue.push(task);
+            }
+        }
+        self.task_policy = policy;
+    }
+
+    /// Set the capacity of the task queue, when the queue is full, the new tasks are handled
+    /// by the [`RejectPolicy`]. The task queue is unbounded by default.
+    ///
+    /// Only the tasks queued in this pool are counted, the capacity is a soft limit when
+    /// multiple threads submit tasks concurrently.
+    pub fn set_task_capacity(&self, task_capacity: usize) {
+        self.task_capacity.store(task_capacity, Ordering::Release);
+    }
+
+    /// Get the capacity of the task queue.
+    pub fn get_task_capacity(&self) -> usize {
+        self.task_capacity.load(Ordering::Acquire)
+    }
+
+    /// Get the policy used when the task queue is full.
+    pub fn reject_policy(&self) -> RejectPolicy {
+        self.reject_policy
+    }
+
+    /// Set the policy used when the task queue is full.
+    pub fn set_reject_policy(&mut self, policy: RejectPolicy) {
+        self.reject_policy = policy;
     }
 
     /// Stop this coroutine pool.
     pub fn stop(&mut self, dur: Duration) -> std::io::Result<()> {
         match self.state() {
-            PoolState::Running => {
-                assert_eq!(PoolState::Running, self.stopping()?);
+            PoolState::Running | PoolState::Stopping => {
+                //可能已被shutdown_now停止
+                _ = self.stopping()?;
                 _ = self.try_timed_schedule_task(dur)?;
                 assert_eq!(PoolState::Stopping, self.stopped()?);
                 Ok(())
             }
-            PoolState::Stopping => Err(Error::new(ErrorKind::Other, "should never happens")),
             PoolState::Stopped => Ok(()),
         }
     }
 
+    /// Stop this pool forcefully, it stops accepting new tasks, asks the running tasks to
+    /// cancel at their next suspension point, and returns the tasks which have never
+    /// started, including the tasks waiting in the timer wheel.
+    ///
+    /// Only the local queue of this pool is drained, so it must be called on the thread which
+    /// schedules this pool, the tasks stolen by other pools keep running there.
+    ///
+    /// The joiners of the returned tasks get [`JoinErrorKind::Cancelled`], the tasks can be
+    /// persisted by their names, run elsewhere by [`Task::run`] or submitted to another pool by
+    /// [`CoroutinePool::resubmit_task`]. The pool still needs to be scheduled or stopped by
+    /// [`CoroutinePool::stop`], then the cancelled tasks unwind.
+    ///
+    /// # Errors
+    /// if change state fails.
+    pub fn shutdown_now(&self) -> std::io::Result<Vec<Task<'p>>> {
+        if PoolState::Stopped == self.state() {
+            return Ok(Vec::new());
+        }
+        //先取出时间轮中的任务，避免停止时被丢弃
+        let mut tasks = self.timers().remove_if(|_| true);
+        self.timer_keys.clear();
+        _ = self.stopping()?;
+        //只取本池的任务，不从其他队列steal
+        tasks.extend(
+            std::mem::take(&mut *self.deadline_queue())
+                .into_values()
+                .chain(std::iter::from_fn(|| self.task_queue.pop_local())),
+        );
+        for task in &tasks {
+            _ = QUEUED_TASKS.remove(task.get_name());
+            _ = CANCEL_TASKS.remove(task.get_name());
+            self.complete(
+                task.get_name(),
+                Err(JoinError::new(JoinErrorKind::Cancelled)),
+            );
+        }
+        //正在执行的任务在下一个挂起点被取消
+        for co_name in self.running_coroutines.iter() {
+            Scheduler::try_cancel_coroutine(co_name.key());
+        }
+        Ok(tasks)
+    }
+
+    /// Submit a task returned by [`CoroutinePool::shutdown_now`] to this pool, its name,
+    /// priority, deadline, due time and period are kept.
+    ///
+    /// # Errors
+    /// see [`CoroutinePool::submit_task`].
+    pub fn resubmit_task(&self, task: Task<'p>) -> std::io::Result<String> {
+        match self.state() {
+            PoolState::Running => {}
+            PoolState::Stopping | PoolState::Stopped => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "The coroutine pool is stopping or stopped !",
+                ))
+            }
+        }
+        let name = self.claim_name(Some(String::from(task.get_name())))?;
+        if let Some(scheduled) = task.scheduled() {
+            self.schedule_raw_task(scheduled, task);
+        } else {
+            self.submit_raw_task(task)?;
+        }
+        Ok(name.accept())
+    }
+
     /// Submit a new task to this pool.
     ///
     /// Allow multiple threads to concurrently submit task to the pool,
     /// but only allow one thread to execute scheduling.
-    pub fn submit_task(
+    ///
+    /// # Errors
+    /// if the pool is stopping or stopped, or the `name` is used by an unfinished task or an
+    /// unclaimed result, or the task queue is full and the [`RejectPolicy::Abort`] is used,
+    /// the latter wraps a [`JoinErrorKind::Rejected`] error.
+    pub fn submit_task<R: Any + Send>(
         &self,
         name: Option<String>,
-        func: impl FnOnce(Option<usize>) -> Option<usize> + 'p,
+        func: impl FnOnce(Option<usize>) -> R + 'p,
         param: Option<usize>,
         priority: Option<c_longlong>,
     ) -> std::io::Result<String> {
@@ -220,58 +501,582 @@ impl<'p> CoroutinePool<'p> {
                 ))
             }
         }
-        let name = name.unwrap_or(format!("{}@{}", self.name(), uuid::Uuid::new_v4()));
-        self.submit_raw_task(Task::new(name.clone(), func, param, priority));
+        let name = self.claim_name(name)?;
+        self.submit_raw_task(
+            Task::new(String::from(name.name()), func, param, priority)
+                .with_locals(InheritableLocals::capture()),
+        )?;
+        Ok(name.accept())
+    }
+
+    /// Submit a new task which should start before the absolute `deadline` in nanoseconds,
+    /// otherwise it will be dropped and the joiner will get [`JoinErrorKind::DeadlineMissed`].
+    ///
+    /// Allow multiple threads to concurrently submit task to the pool,
+    /// but only allow one thread to execute scheduling.
+    ///
+    /// # Errors
+    /// see [`CoroutinePool::submit_task`].
+    pub fn submit_deadline_task<R: Any + Send>(
+        &self,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> R + 'p,
+        param: Option<usize>,
+        priority: Option<c_longlong>,
+        deadline: u64,
+    ) -> std::io::Result<String> {
+        match self.state() {
+            PoolState::Running => {}
+            PoolState::Stopping | PoolState::Stopped => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "The coroutine pool is stopping or stopped !",
+                ))
+            }
+        }
+        let name = self.claim_name(name)?;
+        self.submit_raw_task(
+            Task::new(String::from(name.name()), func, param, priority)
+                .with_deadline(deadline)
+                .with_locals(InheritableLocals::capture()),
+        )?;
+        Ok(name.accept())
+    }
+
+    /// Submit new tasks with the same priority in bulk, they are pushed into the task queue
+    /// at once, which is much cheaper than calling [`CoroutinePool::submit_task`] in a loop.
+    ///
+    /// Returns the names of the tasks in order. If the task queue doesn't have space for all
+    /// of them, the [`RejectPolicy::Abort`] refuses them all, other policies apply to each task.
+    ///
+    /// # Errors
+    /// see [`CoroutinePool::submit_task`].
+    pub fn submit_tasks<R: Any + Send, F: FnOnce(Option<usize>) -> R + 'p>(
+        &self,
+        tasks: impl IntoIterator<Item = (F, Option<usize>)>,
+        priority: Option<c_longlong>,
+    ) -> std::io::Result<Vec<String>> {
+        match self.state() {
+            PoolState::Running => {}
+            PoolState::Stopping | PoolState::Stopped => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "The coroutine pool is stopping or stopped !",
+                ))
+            }
+        }
+        let mut claimed = Vec::new();
+        let mut queued = Vec::new();
+        for (func, param) in tasks {
+            let name = self.claim_name(None)?;
+            queued.push(
+                Task::new(String::from(name.name()), func, param, priority)
+                    .with_locals(InheritableLocals::capture()),
+            );
+            claimed.push(name);
+        }
+        if self.size().saturating_add(queued.len()) > self.get_task_capacity() {
+            if RejectPolicy::Abort == self.reject_policy {
+                return Err(Error::from(JoinError::new(JoinErrorKind::Rejected)));
+            }
+            let mut names = Vec::new();
+            for (task, name) in queued.into_iter().zip(claimed) {
+                self.submit_raw_task(task)?;
+                names.push(name.accept());
+            }
+            return Ok(names);
+        }
+        let names: Vec<String> = claimed.into_iter().map(ClaimedName::accept).collect();
+        let tasks = queued;
+        let priority = priority.unwrap_or(DEFAULT_PRECEDENCE);
+        for name in &names {
+            _ = QUEUED_TASKS.insert(name.clone(), (String::from(self.name()), priority));
+        }
+        self.task_queue.push_batch_with_priority(priority, tasks);
+        self.blocker.notify();
+        Ok(names)
+    }
+
+    /// Submit a new task which runs at the absolute `timestamp` in nanoseconds, it's kept in
+    /// a timer wheel without holding any coroutine until it's due.
+    ///
+    /// The task can be cancelled by [`CoroutinePool::try_cancel_task`] before it runs.
+    ///
+    /// # Errors
+    /// if the pool is stopping or stopped, or the `name` is already in use.
+    pub fn schedule_at<R: Any + Send>(
+        &self,
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> R + 'p,
+        param: Option<usize>,
+        priority: Option<c_longlong>,
+        timestamp: u64,
+    ) -> std::io::Result<String> {
+        match self.state() {
+            PoolState::Running => {}
+            PoolState::Stopping | PoolState::Stopped => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "The coroutine pool is stopping or stopped !",
+                ))
+            }
+        }
+        let name = self.claim_name(name)?.accept();
+        self.schedule_raw_task(
+            timestamp,
+            Task::new(name.clone(), func, param, priority)
+                .with_locals(InheritableLocals::capture()),
+        );
+        Ok(name)
+    }
+
+    /// Submit a new task which runs after `initial_delay`, then runs every `period`.
+    ///
+    /// The task is repeated until it's cancelled by [`CoroutinePool::try_cancel_task`],
+    /// panics or the pool stops, then the joiners will get the [`JoinError`].
+    ///
+    /// # Errors
+    /// if the pool is stopping or stopped, the `name` is already in use, or the `period` is zero.
+    pub fn schedule_with_fixed_rate(
+        &self,
+        name: Option<String>,
+        func: impl FnMut(Option<usize>) + 'p,
+        param: Option<usize>,
+        priority: Option<c_longlong>,
+        initial_delay: Duration,
+        period: Duration,
+    ) -> std::io::Result<String> {
+        self.schedule_periodic(
+            name,
+            func,
+            param,
+            priority,
+            initial_delay,
+            Period::FixedRate(period),
+        )
+    }
+
+    /// Submit a new task which runs after `initial_delay`, then runs again `delay` after
+    /// the previous run finished.
+    ///
+    /// The task is repeated until it's cancelled by [`CoroutinePool::try_cancel_task`],
+    /// panics or the pool stops, then the joiners will get the [`JoinError`].
+    ///
+    /// # Errors
+    /// if the pool is stopping or stopped, the `name` is already in use, or the `delay` is zero.
+    pub fn schedule_with_fixed_delay(
+        &self,
+        name: Option<String>,
+        func: impl FnMut(Option<usize>) + 'p,
+        param: Option<usize>,
+        priority: Option<c_longlong>,
+        initial_delay: Duration,
+        delay: Duration,
+    ) -> std::io::Result<String> {
+        self.schedule_periodic(
+            name,
+            func,
+            param,
+            priority,
+            initial_delay,
+            Period::FixedDelay(delay),
+        )
+    }
+
+    fn schedule_periodic(
+        &self,
+        name: Option<String>,
+        func: impl FnMut(Option<usize>) + 'p,
+        param: Option<usize>,
+        priority: Option<c_longlong>,
+        initial_delay: Duration,
+        period: Period,
+    ) -> std::io::Result<String> {
+        match self.state() {
+            PoolState::Running => {}
+            PoolState::Stopping | PoolState::Stopped => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "The coroutine pool is stopping or stopped !",
+                ))
+            }
+        }
+        if let Period::FixedRate(Duration::ZERO) | Period::FixedDelay(Duration::ZERO) = period {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "The period should be greater than zero !",
+            ));
+        }
+        let name = self.claim_name(name)?.accept();
+        let periodic = Periodic::new(
+            name.clone(),
+            func,
+            param,
+            priority,
+            period,
+            get_timeout_time(initial_delay),
+        );
+        self.schedule_raw_task(periodic.scheduled(), periodic.into_task());
         Ok(name)
     }
 
+    /// Claim the task name, a unique name is generated if it's not given.
+    ///
+    /// The name is released if the returned [`ClaimedName`] is dropped without accepted.
+    fn claim_name(&self, name: Option<String>) -> std::io::Result<ClaimedName> {
+        let name = name.unwrap_or(format!("{}@{}", self.name(), uuid::Uuid::new_v4()));
+        //结果已过期的任务名可以复用
+        _ = self.results.remove_if(&name, |_, (_, result)| {
+            result
+                .as_ref()
+                .is_err_and(|e| JoinErrorKind::Expired == e.kind())
+        });
+        if self.results.contains_key(&name) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("The task name {name} is already in use !"),
+            ));
+        }
+        match TASK_STATUS.entry(name.clone()) {
+            Entry::Occupied(_) => Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("The task name {name} is already in use !"),
+            )),
+            Entry::Vacant(entry) => {
+                _ = entry.insert(TaskStatus::Queued);
+                Ok(ClaimedName(Some(name)))
+            }
+        }
+    }
+
+    fn schedule_raw_task(&self, timestamp: u64, task: Task<'p>) {
+        let task_name = String::from(task.get_name());
+        let key = self
+            .timers()
+            .insert(timestamp, task.with_scheduled(timestamp));
+        _ = self.timer_keys.insert(task_name, key);
+        self.blocker.notify();
+    }
+
+    fn timers(&self) -> MutexGuard<'_, TimerWheel<Task<'p>>> {
+        self.timers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Move the due tasks from the timer wheel to the task queue.
+    fn fire_timers(&self) {
+        if self.timer_keys.is_empty() {
+            return;
+        }
+        let mut timers = self.timers();
+        while let Some((_, task)) = timers.pop_expired(now()) {
+            _ = self.timer_keys.remove(task.get_name());
+            self.push_task(task);
+        }
+    }
+
+    /// Cancel all tasks in the timer wheel, the joiners will get [`JoinErrorKind::Cancelled`].
+    pub(crate) fn cancel_timers(&self) {
+        let tasks = self.timers().remove_if(|_| true);
+        for task in tasks {
+            let task_name = String::from(task.get_name());
+            drop(task);
+            _ = self.timer_keys.remove(&task_name);
+            self.complete(&task_name, Err(JoinError::new(JoinErrorKind::Cancelled)));
+        }
+    }
+
     /// Submit new task to this pool.
     ///
     /// Allow multiple threads to concurrently submit task to the pool,
     /// but only allow one thread to execute scheduling.
-    pub(crate) fn submit_raw_task(&self, task: Task<'p>) {
-        self.task_queue.push(task);
-        self.blocker.notify();
+    ///
+    /// # Errors
+    /// if the task was rejected.
+    pub(crate) fn submit_raw_task(&self, task: Task<'p>) -> std::io::Result<()> {
+        if let Some(task) = self.admit(task)? {
+            self.push_task(task);
+            self.blocker.notify();
+        }
+        Ok(())
+    }
+
+    /// Apply the [`RejectPolicy`] if the task queue is full, returns the task if it should
+    /// be queued.
+    fn admit(&self, task: Task<'p>) -> std::io::Result<Option<Task<'p>>> {
+        if self.size() < self.get_task_capacity() {
+            return Ok(Some(task));
+        }
+        match self.reject_policy {
+            RejectPolicy::Abort => Err(Error::from(JoinError::new(JoinErrorKind::Rejected))),
+            RejectPolicy::CallerRuns => {
+                self.run_task(task);
+                Ok(None)
+            }
+            RejectPolicy::DiscardOldest => {
+                if let Some(oldest) = self.pop_task() {
+                    self.notify_space();
+                    let task_name = String::from(oldest.get_name());
+                    drop(oldest);
+                    _ = CANCEL_TASKS.remove(&task_name);
+                    self.complete(&task_name, Err(JoinError::new(JoinErrorKind::Rejected)));
+                }
+                Ok(Some(task))
+            }
+            RejectPolicy::Block => {
+                //序号决定唤醒顺序，被唤醒后仍无空间时以原序号重新登记
+                let waiter = SpaceWaiter(self, self.space_seq.fetch_add(1, Ordering::Relaxed));
+                let unparker = Scheduler::unparker();
+                loop {
+                    //先登记再检查，之后的唤醒不会丢失
+                    _ = self.space_waiters().insert(waiter.1, unparker.clone());
+                    let admitted = self.size() < self.get_task_capacity();
+                    if admitted || PoolState::Running != self.state() {
+                        waiter.leave(admitted);
+                        return if admitted {
+                            Ok(Some(task))
+                        } else {
+                            Err(Error::from(JoinError::new(JoinErrorKind::Rejected)))
+                        };
+                    }
+                    Scheduler::park();
+                }
+            }
+        }
+    }
+
+    fn space_waiters(&self) -> MutexGuard<'_, BTreeMap<u64, Unparker>> {
+        self.space_waiters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Wake up a coroutine or thread which is waiting for the space of the task queue.
+    fn notify_space(&self) {
+        //持有锁时唤醒，等待者发现自己被移除时唤醒已经发生
+        if let Some((_, unparker)) = self.space_waiters().pop_first() {
+            unparker.unpark();
+        }
+    }
+
+    /// Wake up all the coroutines and threads which are waiting for the space of the task
+    /// queue, such as when the pool is stopping.
+    fn notify_all_space(&self) {
+        let mut waiters = self.space_waiters();
+        for (_, unparker) in std::mem::take(&mut *waiters) {
+            unparker.unpark();
+        }
+    }
+
+    /// Attempt to obtain task results with the given `task_name`,
+    /// use [`Box::downcast`] to get the typed value.
+    pub fn try_get_task_result(
+        &self,
+        task_name: &str,
+    ) -> Option<Result<Box<dyn Any + Send>, JoinError>> {
+        self.results.remove(task_name).map(|(_, (_, r))| r)
+    }
+
+    /// Get the status of the task with the given `task_name`, returns `None` if the task is
+    /// unknown, or its result has been retrieved, detached or expired.
+    pub fn task_status(&self, task_name: &str) -> Option<TaskStatus> {
+        if let Some(status) = TASK_STATUS.get(task_name) {
+            return Some(*status);
+        }
+        self.results
+            .get(task_name)
+            .and_then(|entry| match &entry.1 {
+                Ok(_) => Some(TaskStatus::Completed),
+                Err(e) if JoinErrorKind::Expired == e.kind() => None,
+                Err(_) => Some(TaskStatus::Failed),
+            })
+    }
+
+    /// List the tasks submitted to this pool that are still in the task queue, with their
+    /// priorities, the tasks with higher precedence come first.
+    ///
+    /// The tasks waiting in the timer wheel are not included.
+    pub fn pending_tasks(&self) -> Vec<(String, c_longlong)> {
+        let mut tasks: Vec<(String, c_longlong)> = QUEUED_TASKS
+            .iter()
+            .filter(|entry| entry.value().0 == self.name())
+            .map(|entry| (entry.key().clone(), entry.value().1))
+            .collect();
+        tasks.sort_by_key(|(_, priority)| *priority);
+        tasks
+    }
+
+    /// Update the status of the task running in the worker coroutine.
+    pub(crate) fn running_task_changed(local: &CoroutineLocal, status: TaskStatus) {
+        if let Some(task_name) = local.get::<String>(RUNNING_TASK) {
+            if let Some(mut current) = TASK_STATUS.get_mut(task_name) {
+                *current = status;
+            }
+        }
+    }
+
+    /// Detach the task with the given `task_name`, its result will be dropped when it
+    /// completes instead of waiting to be retrieved, so the task can no longer be joined.
+    ///
+    /// # Errors
+    /// if the task is unknown or its result has been retrieved, or it already has a
+    /// callback.
+    pub fn detach_task(&self, task_name: &str) -> std::io::Result<()> {
+        self.add_callback(task_name, Box::new(|_, result| drop(result)))
+    }
+
+    /// Attach a callback which is called with the result when the task with the given
+    /// `task_name` completes, it runs in the coroutine or thread which finished the task,
+    /// so it should not block. If the task has already completed, the callback runs now.
+    ///
+    /// The result is consumed by the callback, so the task can no longer be joined.
+    ///
+    /// # Errors
+    /// if the task is unknown or its result has been retrieved, or it already has a
+    /// callback.
+    pub fn on_complete(
+        &self,
+        task_name: &str,
+        callback: impl FnOnce(Result<Box<dyn Any + Send>, JoinError>) + 'p,
+    ) -> std::io::Result<()> {
+        self.add_callback(task_name, Box::new(move |_, result| callback(result)))
+    }
+
+    fn add_callback(&self, task_name: &str, callback: Callback<'p>) -> std::io::Result<()> {
+        //每个任务只能有一个回调，替换会丢失之前的回调
+        match self.callbacks.entry(String::from(task_name)) {
+            Entry::Occupied(_) => {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("The task {task_name} already has a callback !"),
+                ))
+            }
+            Entry::Vacant(entry) => _ = entry.insert(callback),
+        }
+        if !self.results.contains_key(task_name) && TASK_STATUS.contains_key(task_name) {
+            return Ok(());
+        }
+        //任务可能已经完成，也可能是未知的
+        let Some((_, callback)) = self.callbacks.remove(task_name) else {
+            //回调已被执行
+            return Ok(());
+        };
+        if let Some((_, (_, result))) = self.results.remove(task_name) {
+            callback(self, result);
+            return Ok(());
+        }
+        Err(Error::new(
+            ErrorKind::NotFound,
+            format!("The task {task_name} is unknown or its result has been retrieved !"),
+        ))
+    }
+
+    /// Submit a new task which runs with the result of the task with the given `task_name`
+    /// after it completes, then the tasks can be chained into a pipeline without blocking.
+    ///
+    /// Returns the name of the new task, the result of the previous task is consumed,
+    /// see [`CoroutinePool::on_complete`].
+    ///
+    /// # Errors
+    /// if the task is unknown or its result has been retrieved, or it already has a
+    /// callback.
+    pub fn then<R: Any + Send>(
+        &self,
+        task_name: &str,
+        func: impl FnOnce(Result<Box<dyn Any + Send>, JoinError>) -> R + 'p,
+        priority: Option<c_longlong>,
+    ) -> std::io::Result<String> {
+        let name = format!("{}@{}", self.name(), uuid::Uuid::new_v4());
+        let next = name.clone();
+        let locals = InheritableLocals::capture();
+        //新任务在等待期间也可被取消
+        _ = TASK_STATUS.insert(name.clone(), TaskStatus::Queued);
+        self.add_callback(
+            task_name,
+            Box::new(move |pool, result| {
+                let task = Task::new(next.clone(), move |_| func(result), None, priority)
+                    .with_locals(locals);
+                if PoolState::Running != pool.state() || pool.submit_raw_task(task).is_err() {
+                    pool.complete(&next, Err(JoinError::new(JoinErrorKind::Rejected)));
+                }
+            }),
+        )
+        .inspect_err(|_| _ = TASK_STATUS.remove(&name))?;
+        Ok(name)
     }
 
-    /// Attempt to obtain task results with the given `task_name`.
-    pub fn try_get_task_result(&self, task_name: &str) -> Option<Result<Option<usize>, &'p str>> {
-        self.results.remove(task_name).map(|(_, r)| r)
+    /// Cancel the task with the given `task_name`.
+    ///
+    /// If the task is still in the task queue, it will be dropped without running;
+    /// if the task is running, it will unwind at its next suspension point.
+    /// If the task has already finished, nothing happens.
+    pub fn try_cancel_task(&self, task_name: &str) {
+        if self.results.contains_key(task_name) {
+            return;
+        }
+        if let Some((_, key)) = self.timer_keys.remove(task_name) {
+            let task = self.timers().cancel(key);
+            if let Some(task) = task {
+                drop(task);
+                self.complete(task_name, Err(JoinError::new(JoinErrorKind::Cancelled)));
+                return;
+            }
+        }
+        if let Some(co_name) = RUNNING_TASKS.get(task_name) {
+            //任务可能不再挂起，记录下来，结束后不再调度周期任务
+            _ = CANCEL_TASKS.insert(String::from(task_name));
+            Scheduler::try_cancel_coroutine(co_name.value());
+            return;
+        }
+        //只记录排队中和执行中的任务，未知或已结束的任务不记录
+        if !TASK_STATUS.contains_key(task_name) {
+            return;
+        }
+        _ = CANCEL_TASKS.insert(String::from(task_name));
+        //任务可能恰好开始执行或已结束
+        if let Some(co_name) = RUNNING_TASKS.get(task_name) {
+            Scheduler::try_cancel_coroutine(co_name.value());
+        } else if !TASK_STATUS.contains_key(task_name) {
+            _ = CANCEL_TASKS.remove(task_name);
+        }
     }
 
     /// Use the given `task_name` to obtain task results, and if no results are found,
     /// block the current thread for `wait_time`.
     ///
+    /// If timeout, [`JoinErrorKind::TimedOut`] will be returned and the task may still be running.
+    ///
     /// # Errors
-    /// if timeout
+    /// if wait failed.
     pub fn wait_task_result(
         &self,
         task_name: &str,
         wait_time: Duration,
-    ) -> std::io::Result<Result<Option<usize>, &str>> {
-        let key = Box::leak(Box::from(task_name));
-        if let Some(r) = self.try_get_task_result(key) {
-            self.notify(key);
-            drop(self.waits.remove(key));
+    ) -> std::io::Result<Result<Box<dyn Any + Send>, JoinError>> {
+        if let Some(r) = self.try_get_task_result(task_name) {
+            self.notify(task_name);
+            drop(self.waits.remove(task_name));
             return Ok(r);
         }
         if SchedulableCoroutine::current().is_some() {
             let timeout_time = get_timeout_time(wait_time);
             loop {
                 _ = self.try_run();
-                if let Some(r) = self.try_get_task_result(key) {
+                if let Some(r) = self.try_get_task_result(task_name) {
                     return Ok(r);
                 }
                 if timeout_time.saturating_sub(now()) == 0 {
-                    return Err(Error::new(ErrorKind::TimedOut, "wait timeout"));
+                    return Ok(Err(JoinError::new(JoinErrorKind::TimedOut)));
                 }
             }
         }
-        let arc = if let Some(arc) = self.waits.get(key) {
+        let arc = if let Some(arc) = self.waits.get(task_name) {
             arc.clone()
         } else {
             let arc = Arc::new((Mutex::new(true), Condvar::new()));
-            assert!(self.waits.insert(key, arc.clone()).is_none());
+            assert!(self
+                .waits
+                .insert(String::from(task_name), arc.clone())
+                .is_none());
             arc
         };
         let (lock, cvar) = &*arc;
@@ -284,12 +1089,37 @@ impl<'p> CoroutinePool<'p> {
             )
             .map_err(|e| Error::new(ErrorKind::Other, format!("{e}")))?,
         );
-        if let Some(r) = self.try_get_task_result(key) {
-            self.notify(key);
-            assert!(self.waits.remove(key).is_some());
+        if let Some(r) = self.try_get_task_result(task_name) {
+            self.notify(task_name);
+            assert!(self.waits.remove(task_name).is_some());
             return Ok(r);
         }
-        Err(Error::new(ErrorKind::TimedOut, "wait timeout"))
+        Ok(Err(JoinError::new(JoinErrorKind::TimedOut)))
+    }
+
+    /// Store the result of the task and wake up the joiners, if a callback has been attached
+    /// by [`CoroutinePool::on_complete`], the result is passed to the callback instead.
+    fn complete(&self, task_name: &str, result: Result<Box<dyn Any + Send>, JoinError>) {
+        if let Some((_, callback)) = self.callbacks.remove(task_name) {
+            _ = TASK_STATUS.remove(task_name);
+            _ = CANCEL_TASKS.remove(task_name);
+            _ = catch!(
+                || callback(self, result),
+                format!("the callback of task {task_name}")
+            );
+            return;
+        }
+        self.expire_results(&self.results, &self.next_expire);
+        if self
+            .results
+            .insert(String::from(task_name), (now(), result))
+            .is_some()
+        {
+            error!("The previous result of task {task_name} was not retrieved and is dropped !");
+        }
+        _ = TASK_STATUS.remove(task_name);
+        _ = CANCEL_TASKS.remove(task_name);
+        self.notify(task_name);
     }
 
     fn can_recycle(&self) -> bool {
@@ -304,7 +1134,7 @@ impl<'p> CoroutinePool<'p> {
     /// # Errors
     /// if create failed.
     fn try_grow(&self) -> std::io::Result<()> {
-        if self.task_queue.is_empty() {
+        if self.is_empty() {
             // No task to run
             trace!("The coroutine pool:{} has no task !", self.name());
             return Ok(());
@@ -323,7 +1153,7 @@ impl<'p> CoroutinePool<'p> {
                         && running > pool.get_min_size()
                         || pool.can_recycle()
                     {
-                        return None;
+                        return;
                     }
                     _ = pool.pop_fail_times.fetch_add(1, Ordering::Release);
                     match pool.pop_fail_times.load(Ordering::Acquire).cmp(&running) {
@@ -340,18 +1170,20 @@ impl<'p> CoroutinePool<'p> {
             None,
             None,
         )
+        //工作协程的结果无人关心
+        .map(|co_id| self.detach_co(co_id))
     }
 
     /// Try to create a coroutine in this pool.
     ///
     /// # Errors
     /// if create failed.
-    pub fn submit_co(
+    pub fn submit_co<R: Any + Send>(
         &self,
-        f: impl FnOnce(&Suspender<(), ()>, ()) -> Option<usize> + 'static,
+        f: impl FnOnce(&Suspender<(), ()>, ()) -> R + 'static,
         stack_size: Option<usize>,
         priority: Option<c_longlong>,
-    ) -> std::io::Result<()> {
+    ) -> std::io::Result<CoroutineId> {
         if self.get_running_size() >= self.get_max_size() {
             trace!(
                 "The coroutine pool:{} has reached its maximum size !",
@@ -362,26 +1194,153 @@ impl<'p> CoroutinePool<'p> {
                 "The coroutine pool has reached its maximum size !",
             ));
         }
-        self.deref().submit_co(f, stack_size, priority).map(|()| {
-            _ = self.running.fetch_add(1, Ordering::Release);
-        })
+        self.deref()
+            .submit_co(f, stack_size, priority)
+            .inspect(|_| {
+                _ = self.running.fetch_add(1, Ordering::Release);
+            })
     }
 
     fn reset_pop_fail_times(&self) {
         self.pop_fail_times.store(0, Ordering::Release);
     }
 
+    fn deadline_queue(&self) -> MutexGuard<'_, BTreeMap<(u64, u64), Task<'p>>> {
+        self.deadline_queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn push_task(&self, task: Task<'p>) {
+        _ = QUEUED_TASKS.insert(
+            String::from(task.get_name()),
+            (
+                String::from(self.name()),
+                task.priority().unwrap_or(DEFAULT_PRECEDENCE),
+            ),
+        );
+        self.task_queue.push(task);
+    }
+
+    fn pop_task(&self) -> Option<Task<'p>> {
+        self.pick_task()
+            .inspect(|task| _ = QUEUED_TASKS.remove(task.get_name()))
+    }
+
+    fn pick_task(&self) -> Option<Task<'p>> {
+        if TaskPolicy::Priority == self.task_policy {
+            return self.task_queue.pop();
+        }
+        let mut deadline_queue = self.deadline_queue();
+        //只取本地队列中的任务排序，为空时才从其他队列steal
+        let mut count = self.task_queue.len();
+        if deadline_queue.is_empty() {
+            count = count.max(1);
+        }
+        for _ in 0..count {
+            if let Some(task) = self.task_queue.pop() {
+                let seq = self.deadline_seq.fetch_add(1, Ordering::Relaxed);
+                _ = deadline_queue.insert((task.deadline().unwrap_or(u64::MAX), seq), task);
+            }
+        }
+        deadline_queue.pop_first().map(|(_, task)| task)
+    }
+
     fn try_run(&self) -> Option<()> {
-        self.task_queue.pop().map(|task| {
-            let (task_name, result) = task.run();
-            assert!(
-                self.results.insert(task_name.clone(), result).is_none(),
-                "The previous result was not retrieved in a timely manner"
-            );
-            self.notify(&task_name);
+        self.fire_timers();
+        self.pop_task().map(|task| {
+            self.notify_space();
+            self.run_task(task);
         })
     }
 
+    fn run_task(&self, mut task: Task<'p>) {
+        let co_name = SchedulableCoroutine::current().map(|co| {
+            let co_name = String::from(co.name());
+            _ = RUNNING_TASKS.insert(String::from(task.get_name()), co_name.clone());
+            _ = self.running_coroutines.insert(co_name.clone());
+            _ = co.put(RUNNING_TASK, String::from(task.get_name()));
+            co_name
+        });
+        let rejected = if CANCEL_TASKS.remove(task.get_name()).is_some() {
+            //任务在执行前已被取消
+            Some(JoinErrorKind::Cancelled)
+        } else if task.deadline().is_some_and(|deadline| deadline < now()) {
+            //任务在执行前已错过截止时间
+            Some(JoinErrorKind::DeadlineMissed)
+        } else {
+            None
+        };
+        if let Some(kind) = rejected {
+            let task_name = String::from(task.get_name());
+            drop(task);
+            if let Some(co_name) = co_name {
+                _ = RUNNING_TASKS.remove(&task_name);
+                _ = self.running_coroutines.remove(&co_name);
+                if let Some(co) = SchedulableCoroutine::current() {
+                    _ = co.remove::<String>(RUNNING_TASK);
+                }
+                Scheduler::clean_cancel(&co_name);
+            }
+            self.complete(&task_name, Err(JoinError::new(kind)));
+            return;
+        }
+        if let Some(mut status) = TASK_STATUS.get_mut(task.get_name()) {
+            *status = TaskStatus::Running;
+        }
+        let periodic = task.periodic().cloned();
+        let locals = task.take_locals();
+        let (task_name, mut result) = CoroutineLocal::scoped(locals, || task.run());
+        let cancelled = Suspender::<(), ()>::take_cancelled();
+        //执行期间被取消，但可能没有挂起过
+        let stopped = CANCEL_TASKS.remove(&task_name).is_some();
+        if let Some(co_name) = co_name {
+            _ = RUNNING_TASKS.remove(&task_name);
+            _ = self.running_coroutines.remove(&co_name);
+            if let Some(co) = SchedulableCoroutine::current() {
+                _ = co.remove::<String>(RUNNING_TASK);
+            }
+            Scheduler::clean_cancel(&co_name);
+        }
+        if cancelled {
+            result = Err(JoinError::new(JoinErrorKind::Cancelled));
+        }
+        if let Some(periodic) = periodic {
+            //周期任务正常结束且未被取消，再次放入时间轮
+            if stopped {
+                result = Err(JoinError::new(JoinErrorKind::Cancelled));
+            }
+            if result.is_ok() && PoolState::Running == self.state() {
+                let periodic = periodic.next(now());
+                if let Some(mut status) = TASK_STATUS.get_mut(&task_name) {
+                    *status = TaskStatus::Queued;
+                }
+                self.schedule_raw_task(periodic.scheduled(), periodic.into_task());
+                return;
+            }
+            if result.is_ok() {
+                result = Err(JoinError::new(JoinErrorKind::Cancelled));
+            }
+        }
+        self.complete(&task_name, result);
+    }
+
+    /// The worker coroutine exited abnormally while running a task, usually caused by
+    /// stack overflow, then the task fails with the error of the coroutine.
+    pub(crate) fn fail_running_task(&self, co_name: &str, error: JoinError) {
+        let Some(task_name) = RUNNING_TASKS
+            .iter()
+            .find(|entry| entry.value() == co_name)
+            .map(|entry| entry.key().clone())
+        else {
+            return;
+        };
+        _ = RUNNING_TASKS.remove(&task_name);
+        _ = self.running_coroutines.remove(co_name);
+        Scheduler::clean_cancel(co_name);
+        self.complete(&task_name, Err(error));
+    }
+
     fn notify(&self, task_name: &str) {
         if let Some(arc) = self.waits.get(task_name) {
             let (lock, cvar) = &**arc;
@@ -425,6 +1384,7 @@ impl<'p> CoroutinePool<'p> {
     pub fn try_timeout_schedule_task(&mut self, timeout_time: u64) -> std::io::Result<u64> {
         match self.state() {
             PoolState::Running | PoolState::Stopping => {
+                self.fire_timers();
                 drop(self.try_grow());
             }
             PoolState::Stopped => {
@@ -8,7 +8,12 @@ impl CoroutinePool<'_> {
     /// # Errors
     /// if change state fails.
     pub(crate) fn stopping(&self) -> std::io::Result<PoolState> {
-        self.change_state(PoolState::Running, PoolState::Stopping)
+        let state = self.change_state(PoolState::Running, PoolState::Stopping)?;
+        //停止后不再运行延迟任务和周期任务
+        self.cancel_timers();
+        //阻塞的提交者不再等待
+        self.notify_all_space();
+        Ok(state)
     }
 
     /// stopping -> stopped
@@ -1,10 +1,18 @@
 use crate::catch;
+use crate::common::join_error::JoinError;
 use crate::common::ordered_work_steal::Ordered;
+use crate::coroutine::local::InheritableLocals;
+use std::any::Any;
 use std::ffi::c_longlong;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 
 /// 做C兼容时会用到
 pub type UserTaskFunc = extern "C" fn(usize) -> usize;
 
+/// 做C兼容时会用到，用于释放`UserTaskFunc`返回的结果
+pub type UserTaskResultDrop = extern "C" fn(usize);
+
 /// The task impls.
 #[repr(C)]
 #[derive(educe::Educe)]
@@ -12,39 +20,169 @@ pub type UserTaskFunc = extern "C" fn(usize) -> usize;
 pub struct Task<'t> {
     name: String,
     #[educe(Debug(ignore))]
-    func: Box<dyn FnOnce(Option<usize>) -> Option<usize> + 't>,
+    func: Box<dyn FnOnce(Option<usize>) -> Box<dyn Any + Send> + 't>,
     param: Option<usize>,
     priority: Option<c_longlong>,
+    deadline: Option<u64>,
+    //放入时间轮时的计划时间
+    scheduled: Option<u64>,
+    locals: InheritableLocals,
+    periodic: Option<Periodic<'t>>,
+}
+
+/// How a periodic task is repeated.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Period {
+    /// Run every period since the first run, if a run finishes late, the missed runs are
+    /// skipped and the next run starts immediately.
+    FixedRate(Duration),
+    /// Run again after the delay since the previous run finished.
+    FixedDelay(Duration),
+}
+
+/// The periodic part of a task, every run creates a new [`Task`] which shares the function.
+#[derive(educe::Educe)]
+#[educe(Debug, Clone)]
+pub(crate) struct Periodic<'t> {
+    name: String,
+    #[educe(Debug(ignore))]
+    func: Arc<Mutex<dyn FnMut(Option<usize>) + 't>>,
+    param: Option<usize>,
+    priority: Option<c_longlong>,
+    period: Period,
+    //本次运行的计划时间
+    scheduled: u64,
+}
+
+impl<'t> Periodic<'t> {
+    /// Create a new `Periodic` instance which runs first at the `first` timestamp.
+    pub(crate) fn new(
+        name: String,
+        func: impl FnMut(Option<usize>) + 't,
+        param: Option<usize>,
+        priority: Option<c_longlong>,
+        period: Period,
+        first: u64,
+    ) -> Self {
+        Periodic {
+            name,
+            func: Arc::new(Mutex::new(func)),
+            param,
+            priority,
+            period,
+            scheduled: first,
+        }
+    }
+
+    /// Get the timestamp of this run.
+    pub(crate) fn scheduled(&self) -> u64 {
+        self.scheduled
+    }
+
+    /// Move to the next run, `finished` is the timestamp when this run finished.
+    #[must_use]
+    pub(crate) fn next(mut self, finished: u64) -> Self {
+        let nanos = |dur: Duration| u64::try_from(dur.as_nanos()).unwrap_or(u64::MAX);
+        self.scheduled = match self.period {
+            Period::FixedRate(period) => self.scheduled.saturating_add(nanos(period)).max(finished),
+            Period::FixedDelay(delay) => finished.saturating_add(nanos(delay)),
+        };
+        self
+    }
+
+    /// Create the task of this run.
+    pub(crate) fn into_task(self) -> Task<'t> {
+        let func = self.func.clone();
+        let mut task = Task::new(
+            self.name.clone(),
+            move |param| (func.lock().unwrap_or_else(PoisonError::into_inner))(param),
+            self.param,
+            self.priority,
+        );
+        task.periodic = Some(self);
+        task
+    }
 }
 
 impl<'t> Task<'t> {
     /// Create a new `Task` instance.
-    pub fn new(
+    pub fn new<R: Any + Send>(
         name: String,
-        func: impl FnOnce(Option<usize>) -> Option<usize> + 't,
+        func: impl FnOnce(Option<usize>) -> R + 't,
         param: Option<usize>,
         priority: Option<c_longlong>,
     ) -> Self {
         Task {
             name,
-            func: Box::new(func),
+            func: Box::new(move |param| Box::new(func(param))),
             param,
             priority,
+            deadline: None,
+            scheduled: None,
+            locals: InheritableLocals::default(),
+            periodic: None,
         }
     }
 
+    /// Set the absolute deadline in nanoseconds, the task will be dropped if it can't start
+    /// before the deadline.
+    #[must_use]
+    pub fn with_deadline(mut self, deadline: u64) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Get the absolute deadline of this task.
+    #[must_use]
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Set the absolute timestamp in nanoseconds when the task is due.
+    #[must_use]
+    pub(crate) fn with_scheduled(mut self, scheduled: u64) -> Self {
+        self.scheduled = Some(scheduled);
+        self
+    }
+
+    /// Get the absolute timestamp when the task is due, it's `None` if the task is not
+    /// scheduled by the timer wheel.
+    pub(crate) fn scheduled(&self) -> Option<u64> {
+        self.scheduled
+    }
+
+    /// Set the coroutine locals inherited from the parent.
+    #[must_use]
+    pub fn with_locals(mut self, locals: InheritableLocals) -> Self {
+        self.locals = locals;
+        self
+    }
+
+    /// Take the coroutine locals inherited from the parent.
+    pub(crate) fn take_locals(&mut self) -> InheritableLocals {
+        std::mem::take(&mut self.locals)
+    }
+
+    /// Get the periodic part of this task.
+    pub(crate) fn periodic(&self) -> Option<&Periodic<'t>> {
+        self.periodic.as_ref()
+    }
+
+    /// Get the name of this task.
+    #[must_use]
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
     /// execute the task
     ///
     /// # Errors
     /// if an exception occurred while executing this task.
-    pub fn run<'e>(self) -> (String, Result<Option<usize>, &'e str>) {
+    pub fn run(self) -> (String, Result<Box<dyn Any + Send>, JoinError>) {
         (
             self.name.clone(),
-            catch!(
-                || (self.func)(self.param),
-                format!("task {} failed without message", self.name),
-                format!("task {}", self.name)
-            ),
+            catch!(|| (self.func)(self.param), format!("task {}", self.name)),
         )
     }
 }
@@ -70,7 +208,12 @@ mod tests {
             None,
             None,
         );
-        assert_eq!((String::from("test"), Ok(None)), task.run());
+        let (name, result) = task.run();
+        assert_eq!("test", name);
+        assert_eq!(
+            Some(&None::<usize>),
+            result.expect("task failed").downcast_ref()
+        );
     }
 
     #[test]
@@ -83,9 +226,29 @@ mod tests {
             None,
             None,
         );
+        let (name, result) = task.run();
+        assert_eq!("test", name);
+        let error = result.expect_err("task should panic");
+        assert!(error.is_panic());
+        assert_eq!("test panic, just ignore it", error.message());
+    }
+
+    #[test]
+    fn test_panic_with_string() {
+        let task = Task::new(
+            String::from("test"),
+            |p| {
+                panic!("test panic with {p:?}, just ignore it");
+            },
+            Some(1),
+            None,
+        );
+        let (_, result) = task.run();
+        let error = result.expect_err("task should panic");
+        assert_eq!("test panic with Some(1), just ignore it", error.message());
         assert_eq!(
-            (String::from("test"), Err("test panic, just ignore it")),
-            task.run()
+            Some(&String::from("test panic with Some(1), just ignore it")),
+            error.into_panic().downcast_ref()
         );
     }
 }
@@ -42,6 +42,26 @@ pub enum PoolState {
 
 impl_display_by_debug!(PoolState);
 
+/// Enums used to describe task status
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TaskStatus {
+    /// The task is waiting in the task queue or the timer wheel.
+    Queued,
+    /// The task is running.
+    Running,
+    /// The coroutine running the task is suspended.
+    Suspended,
+    /// The coroutine running the task enters the syscall.
+    InSyscall(SyscallName),
+    /// The task completed and its result has not been retrieved.
+    Completed,
+    /// The task failed and its error has not been retrieved.
+    Failed,
+}
+
+impl_display_by_debug!(TaskStatus);
+
 /// Enums used to describe syscall
 #[allow(non_camel_case_types, missing_docs)]
 #[repr(C)]
@@ -199,6 +219,8 @@ pub enum CoroutineState<Y, R> {
     Complete(R),
     /// The coroutine completed with a error message.
     Error(&'static str),
+    /// The coroutine was cancelled.
+    Cancelled,
 }
 
 impl_display_by_debug!(CoroutineState<Y, R>);
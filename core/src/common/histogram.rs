@@ -0,0 +1,87 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The bucket `i` counts the values in `(2^(i-1), 2^i]`, the bucket 0 counts 0 and 1.
+const BUCKETS: usize = u64::BITS as usize + 1;
+
+/// A lock-free histogram, values are counted in power-of-two buckets.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS],
+    count: AtomicU64,
+    sum: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    /// Record a value.
+    pub fn record(&self, value: u64) {
+        let index = (u64::BITS - value.saturating_sub(1).leading_zeros()) as usize;
+        _ = self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        _ = self.count.fetch_add(1, Ordering::Relaxed);
+        _ = self.sum.fetch_add(value, Ordering::Relaxed);
+        _ = self.max.fetch_max(value, Ordering::Relaxed);
+    }
+
+    /// Returns the number of recorded values.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the max recorded value.
+    pub fn max(&self) -> u64 {
+        self.max.load(Ordering::Relaxed)
+    }
+
+    /// Returns the mean of recorded values.
+    pub fn mean(&self) -> u64 {
+        self.sum
+            .load(Ordering::Relaxed)
+            .checked_div(self.count())
+            .unwrap_or(0)
+    }
+
+    /// Returns the upper bound of the bucket which contains the `percentile`,
+    /// the `percentile` should be in `[0, 100]`.
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        let count = self.count();
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let target = ((count as f64) * percentile.clamp(0.0, 100.0) / 100.0).ceil() as u64;
+        let mut seen = 0;
+        for (bound, n) in self.buckets() {
+            seen += n;
+            if seen >= target.max(1) {
+                return bound.min(self.max());
+            }
+        }
+        0
+    }
+
+    /// Returns the non-empty buckets as `(upper bound, count)`.
+    pub fn buckets(&self) -> Vec<(u64, u64)> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter_map(|(i, n)| {
+                let n = n.load(Ordering::Relaxed);
+                let bound = u32::try_from(i).ok().and_then(|i| 1u64.checked_shl(i));
+                (n > 0).then(|| (bound.unwrap_or(u64::MAX), n))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,296 @@
+use crate::impl_display_by_debug;
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt::{Debug, Display, Formatter};
+use std::io::ErrorKind;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Mutex, Once};
+
+thread_local! {
+    //panic hook在栈展开前捕获的backtrace
+    static PANIC_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Install a panic hook once, which captures the backtrace at the panic point before the
+/// stack unwinds if `RUST_BACKTRACE` is set, then calls the previous hook.
+fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let backtrace = Backtrace::capture();
+            if BacktraceStatus::Captured == backtrace.status() {
+                _ = PANIC_BACKTRACE.try_with(|bt| {
+                    if let Ok(mut bt) = bt.try_borrow_mut() {
+                        *bt = Some(backtrace);
+                    }
+                });
+            }
+            previous(info);
+        }));
+    });
+}
+
+fn take_panic_backtrace() -> Option<Backtrace> {
+    PANIC_BACKTRACE
+        .try_with(|bt| bt.try_borrow_mut().ok().and_then(|mut bt| bt.take()))
+        .ok()
+        .flatten()
+}
+
+/// The kinds of [`JoinError`].
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum JoinErrorKind {
+    /// The task panicked.
+    Panic,
+    /// The task was cancelled.
+    Cancelled,
+    /// Waiting for the result timed out, the task may still be running.
+    TimedOut,
+    /// The task overflowed its stack.
+    StackOverflow,
+    /// The task accessed invalid memory.
+    InvalidMemory,
+    /// The deadline of the task passed before it started, so it was dropped without running.
+    DeadlineMissed,
+    /// The task was rejected by the pool, because the pool was full or not running.
+    Rejected,
+    /// The result was not retrieved within the result TTL, so it was dropped.
+    Expired,
+}
+
+impl_display_by_debug!(JoinErrorKind);
+
+/// The reason why a task or coroutine failed to produce its result.
+pub struct JoinError {
+    kind: JoinErrorKind,
+    message: Cow<'static, str>,
+    //只有在panic时才有，用`Mutex`包装是为了实现`Sync`
+    payload: Option<Mutex<Box<dyn Any + Send>>>,
+    backtrace: Option<Backtrace>,
+}
+
+impl JoinError {
+    /// Run `f` and catch its panic into a `JoinError`, the backtrace will be captured
+    /// at the panic point if `RUST_BACKTRACE` is set.
+    ///
+    /// The first call installs a process-wide panic hook, which chains to the hook set before
+    /// it, so the application's hook still runs. A hook set afterwards replaces it, and then
+    /// the backtrace is no longer captured.
+    ///
+    /// # Errors
+    /// if `f` panicked.
+    pub fn catch_unwind<R>(f: impl FnOnce() -> R) -> Result<R, Self> {
+        install_panic_hook();
+        _ = take_panic_backtrace();
+        let result = std::panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+            let mut error = Self::panic(payload);
+            error.backtrace = take_panic_backtrace();
+            error
+        });
+        _ = take_panic_backtrace();
+        result
+    }
+
+    /// Create a `JoinError` from the panic payload without backtrace,
+    /// use [`JoinError::catch_unwind`] to capture the backtrace at the panic point.
+    #[must_use]
+    pub fn panic(payload: Box<dyn Any + Send>) -> Self {
+        let message = if let Some(msg) = payload.downcast_ref::<&'static str>() {
+            Cow::Borrowed(*msg)
+        } else if let Some(msg) = payload.downcast_ref::<String>() {
+            Cow::Owned(msg.clone())
+        } else {
+            Cow::Borrowed("panicked without message")
+        };
+        JoinError {
+            kind: JoinErrorKind::Panic,
+            message,
+            payload: Some(Mutex::new(payload)),
+            backtrace: None,
+        }
+    }
+
+    /// Create a `JoinError` with the given kind, a [`JoinErrorKind::Panic`] error created
+    /// by this has no payload, use [`JoinError::panic`] to keep the payload.
+    #[must_use]
+    pub fn new(kind: JoinErrorKind) -> Self {
+        let message = match kind {
+            JoinErrorKind::Panic => "no payload",
+            JoinErrorKind::Cancelled => "cancelled",
+            JoinErrorKind::TimedOut => "timed out",
+            JoinErrorKind::StackOverflow => "stack overflow",
+            JoinErrorKind::InvalidMemory => "invalid memory reference",
+            JoinErrorKind::DeadlineMissed => "deadline missed",
+            JoinErrorKind::Rejected => "rejected",
+            JoinErrorKind::Expired => "expired",
+        };
+        JoinError {
+            kind,
+            message: Cow::Borrowed(message),
+            payload: None,
+            backtrace: None,
+        }
+    }
+
+    /// Returns the kind of this error.
+    #[must_use]
+    pub fn kind(&self) -> JoinErrorKind {
+        self.kind
+    }
+
+    /// Returns `true` if the task panicked.
+    #[must_use]
+    pub fn is_panic(&self) -> bool {
+        JoinErrorKind::Panic == self.kind
+    }
+
+    /// Returns `true` if the task was cancelled.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        JoinErrorKind::Cancelled == self.kind
+    }
+
+    /// Returns `true` if waiting for the result timed out.
+    #[must_use]
+    pub fn is_timed_out(&self) -> bool {
+        JoinErrorKind::TimedOut == self.kind
+    }
+
+    /// Returns `true` if the task was rejected by the pool.
+    #[must_use]
+    pub fn is_rejected(&self) -> bool {
+        JoinErrorKind::Rejected == self.kind
+    }
+
+    /// Returns the error message, for panics it's the message of the payload.
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns a static message which describes this error without allocation,
+    /// it's used by [`crate::common::constants::CoroutineState::Error`].
+    pub(crate) fn reason(&self) -> &'static str {
+        match &self.message {
+            Cow::Borrowed(msg) => msg,
+            Cow::Owned(_) => "panicked",
+        }
+    }
+
+    /// Returns the backtrace captured at the panic point.
+    #[must_use]
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.backtrace.as_ref()
+    }
+
+    /// Consumes this error and returns the original panic payload,
+    /// which can be passed to [`std::panic::resume_unwind`].
+    ///
+    /// # Errors
+    /// if the task did not panic, or the error has no payload.
+    pub fn try_into_panic(self) -> Result<Box<dyn Any + Send>, Self> {
+        match self.payload {
+            Some(payload) => Ok(payload
+                .into_inner()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)),
+            None => Err(self),
+        }
+    }
+
+    /// Consumes this error and returns the original panic payload.
+    ///
+    /// # Panics
+    /// if the task did not panic, or the error has no payload.
+    #[must_use]
+    pub fn into_panic(self) -> Box<dyn Any + Send> {
+        self.try_into_panic()
+            .unwrap_or_else(|e| panic!("{e} has no panic payload"))
+    }
+}
+
+impl Debug for JoinError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JoinError")
+            .field("kind", &self.kind)
+            .field("message", &self.message)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for JoinError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_panic() {
+            write!(f, "panicked with {}", self.message)
+        } else {
+            write!(f, "{}", self.message)
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+impl From<JoinError> for std::io::Error {
+    fn from(error: JoinError) -> Self {
+        let kind = match error.kind() {
+            JoinErrorKind::Cancelled => ErrorKind::Interrupted,
+            JoinErrorKind::TimedOut => ErrorKind::TimedOut,
+            JoinErrorKind::Expired => ErrorKind::NotFound,
+            JoinErrorKind::Panic
+            | JoinErrorKind::StackOverflow
+            | JoinErrorKind::InvalidMemory
+            | JoinErrorKind::DeadlineMissed
+            | JoinErrorKind::Rejected => ErrorKind::Other,
+        };
+        std::io::Error::new(kind, error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panic() {
+        let error = JoinError::panic(Box::new(String::from("test panic")));
+        assert!(error.is_panic());
+        assert_eq!("test panic", error.message());
+        assert_eq!("panicked", error.reason());
+        assert_eq!("panicked with test panic", error.to_string());
+        let payload = error.into_panic();
+        assert_eq!(Some(&String::from("test panic")), payload.downcast_ref());
+
+        let error = JoinError::panic(Box::new("static panic"));
+        assert_eq!("static panic", error.reason());
+        let error = JoinError::panic(Box::new(1));
+        assert_eq!("panicked without message", error.message());
+
+        let error = JoinError::new(JoinErrorKind::Panic);
+        assert!(error.is_panic());
+        assert!(error.try_into_panic().is_err());
+    }
+
+    #[test]
+    fn test_catch_unwind() {
+        assert_eq!(Ok(1), JoinError::catch_unwind(|| 1).map_err(|e| e.kind()));
+        let error = JoinError::catch_unwind(|| panic!("test panic, just ignore it"))
+            .expect_err("the panic should be caught");
+        assert_eq!("test panic, just ignore it", error.message());
+    }
+
+    #[test]
+    fn test_into_io_error() {
+        let error = JoinError::new(JoinErrorKind::Cancelled);
+        assert!(error.try_into_panic().is_err());
+        let error = std::io::Error::from(JoinError::new(JoinErrorKind::Cancelled));
+        assert_eq!(ErrorKind::Interrupted, error.kind());
+        let error = error
+            .into_inner()
+            .and_then(|e| e.downcast::<JoinError>().ok())
+            .expect("not a JoinError");
+        assert!(error.is_cancelled());
+    }
+}
@@ -40,18 +40,13 @@ macro_rules! error {
     }
 }
 
-/// Catch panic.
+/// Catch panic, the panic payload is kept in [`crate::common::join_error::JoinError`].
 #[macro_export]
 macro_rules! catch {
-    ($f:expr, $msg:expr, $arg:expr) => {
-        std::panic::catch_unwind(std::panic::AssertUnwindSafe($f)).map_err(|e| {
-            let message = if let Some(msg) = e.downcast_ref::<&'static str>() {
-                *msg
-            } else {
-                $msg.leak()
-            };
-            $crate::error!("{} failed with error:{}", $arg, message);
-            message
+    ($f:expr, $arg:expr) => {
+        $crate::common::join_error::JoinError::catch_unwind($f).map_err(|error| {
+            $crate::error!("{} failed with error:{}", $arg, error);
+            error
         })
     };
 }
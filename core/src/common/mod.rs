@@ -89,6 +89,74 @@ pub mod work_steal;
 ///
 pub mod ordered_work_steal;
 
+/// A histogram with power-of-two buckets.
+///
+/// # Examples
+///
+/// ```
+/// use open_coroutine_core::common::histogram::Histogram;
+///
+/// let histogram = Histogram::default();
+/// histogram.record(3);
+/// histogram.record(1000);
+/// assert_eq!(2, histogram.count());
+/// assert_eq!(1000, histogram.max());
+/// assert_eq!(vec![(4, 1), (1024, 1)], histogram.buckets());
+/// ```
+///
+pub mod histogram;
+
+/// The structured error of tasks and coroutines.
+///
+/// # Examples
+///
+/// ```
+/// use open_coroutine_core::common::join_error::JoinError;
+///
+/// let error = JoinError::panic(Box::new("test panic"));
+/// assert!(error.is_panic());
+/// assert_eq!("test panic", error.message());
+/// let payload = error.into_panic();
+/// assert_eq!(Some(&"test panic"), payload.downcast_ref::<&str>());
+/// ```
+///
+pub mod join_error;
+
+/// A hierarchical timing wheel, which is used to manage the timeouts.
+///
+/// # Examples
+///
+/// ```
+/// use open_coroutine_core::common::timer_wheel::TimerWheel;
+///
+/// let mut wheel = TimerWheel::new(0);
+/// let key = wheel.insert(2_000_000, "cancelled");
+/// _ = wheel.insert(1_000_000, "expired");
+/// assert_eq!(Some("cancelled"), wheel.cancel(key));
+/// assert_eq!(None, wheel.pop_expired(999_999));
+/// assert_eq!(Some((1_000_000, "expired")), wheel.pop_expired(3_000_000));
+/// assert!(wheel.is_empty());
+/// ```
+///
+pub mod timer_wheel;
+
+/// The ready queues which decide the running order.
+///
+/// # Examples
+///
+/// ```
+/// use open_coroutine_core::common::ready_queue::{FifoQueue, LifoSlotQueue, ReadyQueue};
+///
+/// let queue = LifoSlotQueue::new(FifoQueue::default());
+/// queue.push(1);
+/// queue.push_woken(2);
+/// assert_eq!(Some(2), queue.pop());
+/// assert_eq!(Some(1), queue.pop());
+/// assert!(queue.is_empty());
+/// ```
+///
+pub mod ready_queue;
+
 #[cfg(target_os = "linux")]
 extern "C" {
     fn linux_version_code() -> c_int;
@@ -1,11 +1,13 @@
+use crate::common::now;
 use crossbeam_deque::{Injector, Steal};
 use crossbeam_skiplist::SkipMap;
 use rand::Rng;
 use st3::fifo::Worker;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::ffi::c_longlong;
 use std::fmt::Debug;
-use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
 
 /// The highest precedence.
 pub const HIGHEST_PRECEDENCE: c_longlong = c_longlong::MIN;
@@ -22,17 +24,49 @@ pub trait Ordered {
     fn priority(&self) -> Option<c_longlong>;
 }
 
+/// The elements with the same priority.
+#[derive(Debug)]
+struct Level<Q> {
+    queue: Q,
+    /// The last time an element was popped, or the time this level became non-empty.
+    served: AtomicU64,
+}
+
+impl<Q> Level<Q> {
+    fn new(queue: Q) -> Self {
+        Level {
+            queue,
+            served: AtomicU64::new(now()),
+        }
+    }
+
+    fn served(&self) -> u64 {
+        self.served.load(Ordering::Acquire)
+    }
+
+    /// Keep the waiting time of the elements moved from other levels.
+    fn inherit(&self, was_empty: bool, served: u64) {
+        if was_empty {
+            self.served.store(served, Ordering::Release);
+        } else {
+            _ = self.served.fetch_min(served, Ordering::AcqRel);
+        }
+    }
+}
+
 /// Work stealing global queue, shared by multiple threads.
 #[repr(C)]
 #[derive(Debug)]
 pub struct OrderedWorkStealQueue<T: Debug> {
-    shared_queue: SkipMap<c_longlong, Injector<T>>,
+    shared_queue: SkipMap<c_longlong, Level<Injector<T>>>,
     /// Number of pending tasks in the queue. This helps prevent unnecessary
     /// locking in the hot path.
     len: AtomicUsize,
     local_capacity: usize,
-    local_queues: VecDeque<SkipMap<c_longlong, Worker<T>>>,
+    local_queues: VecDeque<SkipMap<c_longlong, Level<Worker<T>>>>,
     index: AtomicUsize,
+    /// The aging threshold in nanoseconds, 0 means disabled.
+    aging_threshold: AtomicU64,
 }
 
 impl<T: Debug> Drop for OrderedWorkStealQueue<T> {
@@ -40,7 +74,7 @@ impl<T: Debug> Drop for OrderedWorkStealQueue<T> {
         if !std::thread::panicking() {
             for local_queue in &self.local_queues {
                 for entry in local_queue {
-                    assert!(entry.value().pop().is_none(), "local queue not empty");
+                    assert!(entry.value().queue.pop().is_none(), "local queue not empty");
                 }
             }
             assert!(self.pop().is_none(), "global queue not empty");
@@ -53,6 +87,26 @@ impl<T: Debug + Ordered> OrderedWorkStealQueue<T> {
     pub fn push(&self, item: T) {
         self.push_with_priority(item.priority().unwrap_or(DEFAULT_PRECEDENCE), item);
     }
+
+    /// Push elements to the global queue, they are grouped by priority.
+    pub fn push_batch(&self, items: impl IntoIterator<Item = T>) {
+        for (priority, items) in group_by_priority(items) {
+            self.push_batch_with_priority(priority, items);
+        }
+    }
+}
+
+fn group_by_priority<T: Ordered>(
+    items: impl IntoIterator<Item = T>,
+) -> BTreeMap<c_longlong, Vec<T>> {
+    let mut groups: BTreeMap<c_longlong, Vec<T>> = BTreeMap::new();
+    for item in items {
+        groups
+            .entry(item.priority().unwrap_or(DEFAULT_PRECEDENCE))
+            .or_default()
+            .push(item);
+    }
+    groups
 }
 
 impl<T: Debug> OrderedWorkStealQueue<T> {
@@ -65,6 +119,57 @@ impl<T: Debug> OrderedWorkStealQueue<T> {
             local_capacity,
             local_queues: (0..local_queues_size).map(|_| SkipMap::new()).collect(),
             index: AtomicUsize::new(0),
+            aging_threshold: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the aging threshold, see [`OrderedWorkStealQueue::set_aging_threshold`].
+    pub fn aging_threshold(&self) -> Option<Duration> {
+        match self.aging_threshold.load(Ordering::Acquire) {
+            0 => None,
+            threshold => Some(Duration::from_nanos(threshold)),
+        }
+    }
+
+    /// Set the aging threshold to prevent the lower priority elements from starving,
+    /// `None` disables the aging, which is the default.
+    ///
+    /// If the elements of a priority have not been popped for longer than the threshold,
+    /// they will be popped before the higher priority elements, so every priority gets at
+    /// least one pop per threshold. The waiting time is kept when the elements are stolen
+    /// between the local queues.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use open_coroutine_core::common::ordered_work_steal::OrderedWorkStealQueue;
+    /// use std::time::Duration;
+    ///
+    /// let queue = OrderedWorkStealQueue::new(1, 32);
+    /// queue.set_aging_threshold(Some(Duration::from_millis(10)));
+    /// let local = queue.local_queue();
+    /// local.push_with_priority(1, 1);
+    /// std::thread::sleep(Duration::from_millis(10));
+    /// local.push_with_priority(0, 0);
+    /// assert_eq!(local.pop(), Some(1));
+    /// assert_eq!(local.pop(), Some(0));
+    /// ```
+    pub fn set_aging_threshold(&self, threshold: Option<Duration>) {
+        let threshold = threshold.map_or(0, |t| u64::try_from(t.as_nanos()).unwrap_or(u64::MAX));
+        self.aging_threshold.store(threshold, Ordering::Release);
+    }
+
+    fn serve<Q>(&self, level: &Level<Q>) {
+        if 0 != self.aging_threshold.load(Ordering::Acquire) {
+            level.served.store(now(), Ordering::Release);
+        }
+    }
+
+    /// Returns the timestamp before which the levels are aged.
+    fn aged_before(&self) -> Option<u64> {
+        match self.aging_threshold.load(Ordering::Acquire) {
+            0 => None,
+            threshold => Some(now().saturating_sub(threshold)),
         }
     }
 
@@ -80,13 +185,97 @@ impl<T: Debug> OrderedWorkStealQueue<T> {
 
     /// Push an element to the global queue.
     pub fn push_with_priority(&self, priority: c_longlong, item: T) {
-        self.shared_queue
-            .get_or_insert_with(priority, Injector::new)
-            .value()
-            .push(item);
+        self.push_with_served(priority, item, None);
+    }
+
+    /// Push elements with the same priority to the global queue, the length is only
+    /// updated once.
+    pub fn push_batch_with_priority(
+        &self,
+        priority: c_longlong,
+        items: impl IntoIterator<Item = T>,
+    ) {
+        self.push_batch_with_served(priority, items, None);
+    }
+
+    fn push_with_served(&self, priority: c_longlong, item: T, served: Option<u64>) {
+        self.push_batch_with_served(priority, std::iter::once(item), served);
+    }
+
+    fn push_batch_with_served(
+        &self,
+        priority: c_longlong,
+        items: impl IntoIterator<Item = T>,
+        served: Option<u64>,
+    ) {
+        let entry = self
+            .shared_queue
+            .get_or_insert_with(priority, || Level::new(Injector::new()));
+        let level = entry.value();
+        let was_empty = level.queue.is_empty();
+        let mut count = 0;
+        for item in items {
+            level.queue.push(item);
+            count += 1;
+        }
+        if was_empty || served.is_some() {
+            level.inherit(was_empty, served.unwrap_or_else(now));
+        }
         //add count
         self.len
-            .store(self.len().saturating_add(1), Ordering::Release);
+            .store(self.len().saturating_add(count), Ordering::Release);
+    }
+
+    /// Pop at most `max` elements from the global queue in priority order, the length is
+    /// only updated once.
+    pub fn pop_batch(&self, max: usize) -> Vec<T> {
+        self.pop_batch_with_priority(max)
+            .into_iter()
+            .map(|(_, item)| item)
+            .collect()
+    }
+
+    fn pop_batch_with_priority(&self, max: usize) -> Vec<(c_longlong, T)> {
+        let mut batch = Vec::new();
+        // Fast path, if len == 0, then there are no values
+        if self.is_empty() {
+            return batch;
+        }
+        if let Some(aged_before) = self.aged_before() {
+            //优先弹出饥饿的优先级
+            for entry in &self.shared_queue {
+                if entry.value().served() <= aged_before {
+                    self.steal_batch_from(*entry.key(), entry.value(), max, &mut batch);
+                }
+            }
+        }
+        for entry in &self.shared_queue {
+            self.steal_batch_from(*entry.key(), entry.value(), max, &mut batch);
+        }
+        // Decrement the count.
+        self.len
+            .store(self.len().saturating_sub(batch.len()), Ordering::Release);
+        batch
+    }
+
+    fn steal_batch_from(
+        &self,
+        priority: c_longlong,
+        level: &Level<Injector<T>>,
+        max: usize,
+        batch: &mut Vec<(c_longlong, T)>,
+    ) {
+        let before = batch.len();
+        while batch.len() < max {
+            match level.queue.steal() {
+                Steal::Success(item) => batch.push((priority, item)),
+                Steal::Retry => {}
+                Steal::Empty => break,
+            }
+        }
+        if batch.len() > before {
+            self.serve(level);
+        }
     }
 
     /// Pop an element from the global queue.
@@ -95,23 +284,40 @@ impl<T: Debug> OrderedWorkStealQueue<T> {
         if self.is_empty() {
             return None;
         }
-        for entry in &self.shared_queue {
-            loop {
-                match entry.value().steal() {
-                    Steal::Success(item) => {
-                        // Decrement the count.
-                        self.len
-                            .store(self.len().saturating_sub(1), Ordering::Release);
+        if let Some(aged_before) = self.aged_before() {
+            //优先弹出饥饿的优先级
+            for entry in &self.shared_queue {
+                if entry.value().served() <= aged_before {
+                    if let Some(item) = self.steal_from(entry.value()) {
                         return Some(item);
                     }
-                    Steal::Retry => {}
-                    Steal::Empty => break,
                 }
             }
         }
+        for entry in &self.shared_queue {
+            if let Some(item) = self.steal_from(entry.value()) {
+                return Some(item);
+            }
+        }
         None
     }
 
+    fn steal_from(&self, level: &Level<Injector<T>>) -> Option<T> {
+        loop {
+            match level.queue.steal() {
+                Steal::Success(item) => {
+                    // Decrement the count.
+                    self.len
+                        .store(self.len().saturating_sub(1), Ordering::Release);
+                    self.serve(level);
+                    return Some(item);
+                }
+                Steal::Retry => {}
+                Steal::Empty => return None,
+            }
+        }
+    }
+
     /// Get a local queue, this method should be called up to `local_queue_size` times.
     ///
     /// # Panics
@@ -144,7 +350,7 @@ pub struct OrderedLocalQueue<'l, T: Debug> {
     tick: AtomicU32,
     shared: &'l OrderedWorkStealQueue<T>,
     stealing: AtomicBool,
-    queue: &'l SkipMap<c_longlong, Worker<T>>,
+    queue: &'l SkipMap<c_longlong, Level<Worker<T>>>,
     len: AtomicUsize,
 }
 
@@ -152,7 +358,7 @@ impl<T: Debug> Drop for OrderedLocalQueue<'_, T> {
     fn drop(&mut self) {
         if !std::thread::panicking() {
             for entry in self.queue {
-                assert!(entry.value().pop().is_none(), "local queue not empty");
+                assert!(entry.value().queue.pop().is_none(), "local queue not empty");
             }
         }
     }
@@ -164,12 +370,20 @@ impl<T: Debug + Ordered> OrderedLocalQueue<'_, T> {
     pub fn push(&self, item: T) {
         self.push_with_priority(item.priority().unwrap_or(DEFAULT_PRECEDENCE), item);
     }
+
+    /// Push elements to the local queue, they are grouped by priority,
+    /// see [`OrderedLocalQueue::push_batch_with_priority`].
+    pub fn push_batch(&self, items: impl IntoIterator<Item = T>) {
+        for (priority, items) in group_by_priority(items) {
+            self.push_batch_with_priority(priority, items);
+        }
+    }
 }
 
 impl<'l, T: Debug> OrderedLocalQueue<'l, T> {
     fn new(
         shared: &'l OrderedWorkStealQueue<T>,
-        queue: &'l SkipMap<c_longlong, Worker<T>>,
+        queue: &'l SkipMap<c_longlong, Level<Worker<T>>>,
     ) -> Self {
         OrderedLocalQueue {
             tick: AtomicU32::new(0),
@@ -262,35 +476,98 @@ impl<'l, T: Debug> OrderedLocalQueue<'l, T> {
     /// ```
     pub fn push_with_priority(&self, priority: c_longlong, item: T) {
         if self.is_full() {
-            self.push_to_global(priority, item);
+            self.push_to_global(priority, std::iter::once(item));
             return;
         }
-        if let Err(item) = self
-            .queue
-            .get_or_insert_with(priority, || Worker::new(self.shared.local_capacity))
-            .value()
-            .push(item)
-        {
-            self.push_to_global(priority, item);
+        let entry = self.queue.get_or_insert_with(priority, || {
+            Level::new(Worker::new(self.shared.local_capacity))
+        });
+        let level = entry.value();
+        let was_empty = level.queue.is_empty();
+        if let Err(item) = level.queue.push(item) {
+            self.push_to_global(priority, std::iter::once(item));
         } else {
+            if was_empty {
+                level.inherit(true, now());
+            }
             //add count
             self.len
                 .store(self.len().saturating_add(1), Ordering::Release);
         }
     }
 
-    fn push_to_global(&self, priority: c_longlong, item: T) {
+    /// Push elements with the same priority to the local queue at once, if the local queue
+    /// is full, first push half to global, then push the rest to global.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use open_coroutine_core::common::ordered_work_steal::OrderedWorkStealQueue;
+    ///
+    /// let queue = OrderedWorkStealQueue::new(1, 4);
+    /// let local = queue.local_queue();
+    /// local.push_batch_with_priority(1, 2..4);
+    /// local.push_batch_with_priority(0, [0, 1]);
+    /// assert!(local.is_full());
+    /// local.push_batch_with_priority(2, 4..6);
+    /// assert!(!queue.is_empty());
+    /// assert_eq!(local.pop_batch(8), (0..6).collect::<Vec<_>>());
+    /// assert!(queue.is_empty());
+    /// ```
+    pub fn push_batch_with_priority(
+        &self,
+        priority: c_longlong,
+        items: impl IntoIterator<Item = T>,
+    ) {
+        let mut items = items.into_iter();
+        if !self.is_full() {
+            let entry = self.queue.get_or_insert_with(priority, || {
+                Level::new(Worker::new(self.shared.local_capacity))
+            });
+            let level = entry.value();
+            let was_empty = level.queue.is_empty();
+            let spare = level
+                .queue
+                .spare_capacity()
+                .min(self.shared.local_capacity.saturating_sub(self.len()));
+            let mut count = 0;
+            level
+                .queue
+                .extend(items.by_ref().take(spare).inspect(|_| count += 1));
+            if count > 0 {
+                if was_empty {
+                    level.inherit(true, now());
+                }
+                //add count
+                self.len
+                    .store(self.len().saturating_add(count), Ordering::Release);
+            }
+        }
+        let mut rest = items.peekable();
+        if rest.peek().is_some() {
+            self.push_to_global(priority, rest);
+        }
+    }
+
+    /// Pop at most `max` elements, see [`OrderedLocalQueue::pop`].
+    pub fn pop_batch(&self, max: usize) -> Vec<T> {
+        (0..max).map_while(|_| self.pop()).collect()
+    }
+
+    fn push_to_global(&self, priority: c_longlong, items: impl IntoIterator<Item = T>) {
         //把本地队列的一半放到全局队列
         let count = self.len() / 2;
         for _ in 0..count {
             for entry in self.queue.iter().rev() {
-                if let Some(item) = entry.value().pop() {
-                    self.shared.push_with_priority(*entry.key(), item);
+                let level = entry.value();
+                if let Some(item) = level.queue.pop() {
+                    self.shared
+                        .push_with_served(*entry.key(), item, Some(level.served()));
                 }
             }
         }
         //直接放到全局队列
-        self.shared.push_with_priority(priority, item);
+        self.shared.push_batch_with_priority(priority, items);
     }
 
     /// Increment the tick
@@ -352,6 +629,9 @@ impl<'l, T: Debug> OrderedLocalQueue<'l, T> {
                 return Some(val);
             }
         }
+        if let Some(val) = self.pop_aged() {
+            return Some(val);
+        }
         if let Some(val) = self.pop_local() {
             return Some(val);
         }
@@ -372,15 +652,16 @@ impl<'l, T: Debug> OrderedLocalQueue<'l, T> {
                         continue;
                     }
                     for entry in another {
-                        let worker = entry.value();
+                        let worker = &entry.value().queue;
                         if worker.is_empty() {
                             //其他队列为空
                             continue;
                         }
                         let into_entry = self.queue.get_or_insert_with(*entry.key(), || {
-                            Worker::new(self.shared.local_capacity)
+                            Level::new(Worker::new(self.shared.local_capacity))
                         });
-                        let into_queue = into_entry.value();
+                        let into_queue = &into_entry.value().queue;
+                        let was_empty = into_queue.is_empty();
                         if worker
                             .stealer()
                             .steal(into_queue, |n| {
@@ -397,6 +678,10 @@ impl<'l, T: Debug> OrderedLocalQueue<'l, T> {
                             })
                             .is_ok()
                         {
+                            //偷来的元素保留等待时间
+                            into_entry
+                                .value()
+                                .inherit(was_empty, entry.value().served());
                             self.release_lock();
                             return self.pop_local();
                         }
@@ -405,22 +690,66 @@ impl<'l, T: Debug> OrderedLocalQueue<'l, T> {
             }
             self.release_lock();
         }
-        //都steal不到，只好从shared里pop
-        self.shared.pop()
+        //都steal不到，只好从shared里批量pop，多余的放入本地队列
+        let mut batch = self
+            .shared
+            .pop_batch_with_priority(self.max_steal().max(1))
+            .into_iter();
+        let (_, item) = batch.next()?;
+        for (priority, item) in batch {
+            self.push_with_priority(priority, item);
+        }
+        Some(item)
     }
 
-    fn pop_local(&self) -> Option<T> {
+    fn pop_aged(&self) -> Option<T> {
+        let aged_before = self.shared.aged_before()?;
+        //优先弹出本地队列中饥饿的优先级
+        for entry in self.queue {
+            if entry.value().served() <= aged_before {
+                if let Some(val) = self.pop_level(entry.value()) {
+                    return Some(val);
+                }
+            }
+        }
+        None
+    }
+
+    /// Pop an element from this local queue only, it never steals from the global queue
+    /// or other local queues.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use open_coroutine_core::common::ordered_work_steal::OrderedWorkStealQueue;
+    ///
+    /// let queue = OrderedWorkStealQueue::new(2, 64);
+    /// let local0 = queue.local_queue();
+    /// local0.push_with_priority(0, 0);
+    /// let local1 = queue.local_queue();
+    /// local1.push_with_priority(1, 1);
+    /// assert_eq!(local1.pop_local(), Some(1));
+    /// assert_eq!(local1.pop_local(), None);
+    /// assert_eq!(local0.pop_local(), Some(0));
+    /// ```
+    pub fn pop_local(&self) -> Option<T> {
         //从本地队列弹出元素
         for entry in self.queue {
-            if let Some(val) = entry.value().pop() {
-                // Decrement the count.
-                self.len
-                    .store(self.len().saturating_sub(1), Ordering::Release);
+            if let Some(val) = self.pop_level(entry.value()) {
                 return Some(val);
             }
         }
         None
     }
+
+    fn pop_level(&self, level: &Level<Worker<T>>) -> Option<T> {
+        let val = level.queue.pop()?;
+        // Decrement the count.
+        self.len
+            .store(self.len().saturating_sub(1), Ordering::Release);
+        self.shared.serve(level);
+        Some(val)
+    }
 }
 
 #[cfg(test)]
@@ -448,4 +777,39 @@ mod tests {
         assert_eq!(local1.pop(), None);
         assert_eq!(queue.pop(), None);
     }
+
+    #[test]
+    fn test_aging() {
+        let queue = OrderedWorkStealQueue::new(1, 64);
+        queue.set_aging_threshold(Some(Duration::from_millis(10)));
+        let local = queue.local_queue();
+        let start = now();
+        local.push_with_priority(LOWEST_PRECEDENCE, LOWEST_PRECEDENCE);
+        loop {
+            local.push_with_priority(HIGHEST_PRECEDENCE, HIGHEST_PRECEDENCE);
+            if local.pop() == Some(LOWEST_PRECEDENCE) {
+                break;
+            }
+            assert!(now() - start < 1_000_000_000, "starved");
+        }
+        assert!(now() - start >= 10_000_000);
+        assert_eq!(local.pop(), Some(HIGHEST_PRECEDENCE));
+        assert_eq!(local.pop(), None);
+    }
+
+    #[test]
+    fn test_aging_global() {
+        let queue = OrderedWorkStealQueue::new(1, 2);
+        queue.set_aging_threshold(Some(Duration::from_millis(10)));
+        let local = queue.local_queue();
+        local.push_with_priority(1, 1);
+        std::thread::sleep(Duration::from_millis(10));
+        // the local queue is full, the aged element is moved to the global queue
+        for i in 2..4 {
+            local.push_with_priority(0, i);
+        }
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+    }
 }
@@ -0,0 +1,170 @@
+use crate::common::ordered_work_steal::{Ordered, OrderedLocalQueue};
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// The max times to pop from the LIFO slot in a row, then the queue will be popped once,
+/// so the elements in the queue are never starved.
+const MAX_LIFO_POLLS: usize = 3;
+
+/// The queue of the ready elements, it decides which element runs next.
+///
+/// Allow multiple threads to concurrently push, but only allow one thread to pop.
+pub trait ReadyQueue<T>: Debug {
+    /// Push an element which was just created or yielded by itself.
+    fn push(&self, item: T);
+
+    /// Push an element which was just woken up by a timer, an event or other elements,
+    /// by default it's the same as [`ReadyQueue::push`].
+    fn push_woken(&self, item: T) {
+        self.push(item);
+    }
+
+    /// Pop the element to run next.
+    fn pop(&self) -> Option<T>;
+
+    /// Returns the number of elements in the queue.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if the queue contains no elements.
+    fn is_empty(&self) -> bool {
+        0 == self.len()
+    }
+}
+
+impl<T: Debug + Ordered> ReadyQueue<T> for OrderedLocalQueue<'_, T> {
+    fn push(&self, item: T) {
+        OrderedLocalQueue::push(self, item);
+    }
+
+    fn pop(&self) -> Option<T> {
+        OrderedLocalQueue::pop(self)
+    }
+
+    fn len(&self) -> usize {
+        OrderedLocalQueue::len(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        OrderedLocalQueue::is_empty(self)
+    }
+}
+
+/// Run the just woken element next, as Go and tokio do, which is friendly to the CPU cache
+/// when elements wake each other up.
+///
+/// The element in the slot is not visible to other threads, so it can't be stolen.
+#[derive(Debug)]
+pub struct LifoSlotQueue<T: Debug, Q: ReadyQueue<T>> {
+    slot: Mutex<Option<T>>,
+    lifo_polls: AtomicUsize,
+    queue: Q,
+}
+
+impl<T: Debug, Q: ReadyQueue<T>> LifoSlotQueue<T, Q> {
+    /// Create a new `LifoSlotQueue` on top of the `queue`.
+    pub fn new(queue: Q) -> Self {
+        LifoSlotQueue {
+            slot: Mutex::new(None),
+            lifo_polls: AtomicUsize::new(0),
+            queue,
+        }
+    }
+
+    fn slot(&self) -> MutexGuard<'_, Option<T>> {
+        self.slot.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Debug, Q: ReadyQueue<T>> ReadyQueue<T> for LifoSlotQueue<T, Q> {
+    fn push(&self, item: T) {
+        self.queue.push(item);
+    }
+
+    fn push_woken(&self, item: T) {
+        //被挤出槽位的元素放回队列
+        if let Some(old) = self.slot().replace(item) {
+            self.queue.push(old);
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        if self.lifo_polls.load(Ordering::Acquire) < MAX_LIFO_POLLS {
+            if let Some(item) = self.slot().take() {
+                _ = self.lifo_polls.fetch_add(1, Ordering::Release);
+                return Some(item);
+            }
+        }
+        self.lifo_polls.store(0, Ordering::Release);
+        self.queue.pop().or_else(|| self.slot().take())
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len() + usize::from(self.slot().is_some())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty() && self.slot().is_none()
+    }
+}
+
+/// A strict FIFO queue which ignores the priorities, the elements are never stolen by
+/// other threads.
+#[derive(Debug)]
+pub struct FifoQueue<T: Debug>(Mutex<VecDeque<T>>);
+
+impl<T: Debug> Default for FifoQueue<T> {
+    fn default() -> Self {
+        FifoQueue(Mutex::new(VecDeque::new()))
+    }
+}
+
+impl<T: Debug> FifoQueue<T> {
+    fn queue(&self) -> MutexGuard<'_, VecDeque<T>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Debug> ReadyQueue<T> for FifoQueue<T> {
+    fn push(&self, item: T) {
+        self.queue().push_back(item);
+    }
+
+    fn pop(&self) -> Option<T> {
+        self.queue().pop_front()
+    }
+
+    fn len(&self) -> usize {
+        self.queue().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lifo_slot() {
+        let queue = LifoSlotQueue::new(FifoQueue::default());
+        queue.push(1);
+        queue.push(2);
+        queue.push_woken(3);
+        queue.push_woken(4);
+        assert_eq!(4, queue.len());
+        // 3 is pushed back to the queue by 4
+        assert_eq!(Some(4), queue.pop());
+        for i in 5..8 {
+            queue.push_woken(i);
+            if 7 == i {
+                // the slot has been polled 3 times in a row
+                assert_eq!(Some(1), queue.pop());
+            }
+            assert_eq!(Some(i), queue.pop());
+        }
+        assert_eq!(Some(2), queue.pop());
+        assert_eq!(Some(3), queue.pop());
+        assert_eq!(None, queue.pop());
+        assert!(queue.is_empty());
+    }
+}
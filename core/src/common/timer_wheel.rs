@@ -0,0 +1,369 @@
+use std::fmt::{Debug, Formatter};
+
+/// The precision of the timer wheel in nanoseconds.
+const TICK: u64 = 1_000_000;
+
+const SLOT_BITS: usize = 6;
+
+const SLOTS: usize = 1 << SLOT_BITS;
+
+const LEVELS: usize = 6;
+
+/// The max ticks that can be represented by the wheel, which is about 2 years, the farther
+/// entries will be rescheduled when they reach the last slot.
+const MAX_TICKS: u64 = (1 << (SLOT_BITS * LEVELS)) - 1;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Location {
+    Slot(usize, usize),
+    Expired,
+    Vacant,
+}
+
+#[derive(Debug)]
+struct Entry<T> {
+    value: Option<T>,
+    timestamp: u64,
+    generation: u32,
+    location: Location,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// The key of an entry in [`TimerWheel`], which can be used to cancel the entry.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct TimerKey {
+    index: usize,
+    generation: u32,
+}
+
+/// A hierarchical timing wheel with millisecond precision, the entries are kept in a slab,
+/// so inserting and cancelling are O(1).
+///
+/// Entries never expire before their timestamps.
+pub struct TimerWheel<T> {
+    entries: Vec<Entry<T>>,
+    vacant: Vec<usize>,
+    slots: [[Option<usize>; SLOTS]; LEVELS],
+    occupied: [u64; LEVELS],
+    expired: Option<usize>,
+    elapsed: u64,
+    len: usize,
+}
+
+impl<T> TimerWheel<T> {
+    /// Create a new timer wheel which starts at the `start` timestamp in nanoseconds.
+    #[must_use]
+    pub fn new(start: u64) -> Self {
+        TimerWheel {
+            entries: Vec::new(),
+            vacant: Vec::new(),
+            slots: [[None; SLOTS]; LEVELS],
+            occupied: [0; LEVELS],
+            expired: None,
+            elapsed: start / TICK,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of entries in the wheel.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the wheel contains no entries.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        0 == self.len
+    }
+
+    /// Insert a value which expires at the `timestamp` in nanoseconds.
+    pub fn insert(&mut self, timestamp: u64, value: T) -> TimerKey {
+        let index = if let Some(index) = self.vacant.pop() {
+            let entry = &mut self.entries[index];
+            entry.value = Some(value);
+            entry.timestamp = timestamp;
+            index
+        } else {
+            self.entries.push(Entry {
+                value: Some(value),
+                timestamp,
+                generation: 0,
+                location: Location::Vacant,
+                prev: None,
+                next: None,
+            });
+            self.entries.len() - 1
+        };
+        self.len += 1;
+        self.schedule(index);
+        TimerKey {
+            index,
+            generation: self.entries[index].generation,
+        }
+    }
+
+    /// Cancel the entry with the given key.
+    ///
+    /// Returns `None` if the entry has been popped or cancelled.
+    pub fn cancel(&mut self, key: TimerKey) -> Option<T> {
+        let entry = self.entries.get(key.index)?;
+        if entry.generation != key.generation || Location::Vacant == entry.location {
+            return None;
+        }
+        self.unlink(key.index);
+        Some(self.release(key.index).1)
+    }
+
+    /// Pop an entry whose timestamp is not after `now`, the timestamp is returned with the value.
+    ///
+    /// The entries are popped in the order of their timestamps, and in the insertion order
+    /// if the timestamps are equal.
+    pub fn pop_expired(&mut self, now: u64) -> Option<(u64, T)> {
+        if self.expired.is_none() {
+            self.advance(now);
+        }
+        let index = self.expired?;
+        self.unlink(index);
+        Some(self.release(index))
+    }
+
+    /// Remove all entries which match the `predicate`, regardless of their timestamps.
+    pub fn remove_if(&mut self, mut predicate: impl FnMut(&T) -> bool) -> Vec<T> {
+        let mut removed = Vec::new();
+        for index in 0..self.entries.len() {
+            if self.entries[index]
+                .value
+                .as_ref()
+                .is_some_and(&mut predicate)
+            {
+                self.unlink(index);
+                removed.push(self.release(index).1);
+            }
+        }
+        removed
+    }
+
+    fn release(&mut self, index: usize) -> (u64, T) {
+        let entry = &mut self.entries[index];
+        entry.generation = entry.generation.wrapping_add(1);
+        entry.location = Location::Vacant;
+        self.vacant.push(index);
+        self.len -= 1;
+        (
+            entry.timestamp,
+            entry.value.take().expect("the entry is vacant"),
+        )
+    }
+
+    fn head(&mut self, location: Location) -> &mut Option<usize> {
+        match location {
+            Location::Slot(level, slot) => &mut self.slots[level][slot],
+            Location::Expired => &mut self.expired,
+            Location::Vacant => unreachable!("the entry is vacant"),
+        }
+    }
+
+    fn link(&mut self, index: usize, location: Location) {
+        let head = *self.head(location);
+        if let Some(head) = head {
+            self.entries[head].prev = Some(index);
+        }
+        let entry = &mut self.entries[index];
+        entry.prev = None;
+        entry.next = head;
+        entry.location = location;
+        *self.head(location) = Some(index);
+        if let Location::Slot(level, slot) = location {
+            self.occupied[level] |= 1 << slot;
+        }
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let entry = &self.entries[index];
+        let (prev, next, location) = (entry.prev, entry.next, entry.location);
+        match prev {
+            Some(prev) => self.entries[prev].next = next,
+            None => *self.head(location) = next,
+        }
+        if let Some(next) = next {
+            self.entries[next].prev = prev;
+        }
+        if let Location::Slot(level, slot) = location {
+            if self.slots[level][slot].is_none() {
+                self.occupied[level] &= !(1 << slot);
+            }
+        }
+    }
+
+    /// Put the entry into the slot according to its timestamp.
+    fn schedule(&mut self, index: usize) {
+        let when = (self.entries[index].timestamp / TICK)
+            .clamp(self.elapsed, self.elapsed.saturating_add(MAX_TICKS));
+        // 从低位开始，第一个与当前时间不同的层级
+        let masked = ((self.elapsed ^ when) | (SLOTS as u64 - 1)).min(MAX_TICKS);
+        let level = (u64::BITS - 1 - masked.leading_zeros()) as usize / SLOT_BITS;
+        let slot = Self::slot_of(when, level);
+        self.link(index, Location::Slot(level, slot));
+    }
+
+    fn slot_of(tick: u64, level: usize) -> usize {
+        #[allow(clippy::cast_possible_truncation)]
+        let slot = ((tick >> (level * SLOT_BITS)) & (SLOTS as u64 - 1)) as usize;
+        slot
+    }
+
+    /// Find the nearest occupied slot, the lower levels always expire earlier.
+    #[allow(clippy::cast_possible_truncation)]
+    fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+        for (level, occupied) in self.occupied.iter().enumerate() {
+            if 0 == *occupied {
+                continue;
+            }
+            let slot_range = 1u64 << (level * SLOT_BITS);
+            let level_range = slot_range << SLOT_BITS;
+            let now_slot = Self::slot_of(self.elapsed, level);
+            let distance = occupied.rotate_right(now_slot as u32).trailing_zeros() as usize;
+            let slot = (now_slot + distance) % SLOTS;
+            let mut deadline = (self.elapsed & !(level_range - 1)) + slot as u64 * slot_range;
+            if deadline < self.elapsed {
+                // 已绕过一圈
+                deadline += level_range;
+            }
+            return Some((level, slot, deadline));
+        }
+        None
+    }
+
+    /// Move the entries whose timestamps are not after `now` to the expired list.
+    fn advance(&mut self, now: u64) {
+        let target = now / TICK;
+        loop {
+            let Some((level, slot, deadline)) = self
+                .next_expiration()
+                .filter(|(_, _, deadline)| *deadline <= target)
+            else {
+                self.elapsed = self.elapsed.max(target);
+                return;
+            };
+            self.elapsed = deadline;
+            let mut next = self.slots[level][slot].take();
+            self.occupied[level] &= !(1 << slot);
+            let mut expired = Vec::new();
+            while let Some(index) = next {
+                next = self.entries[index].next;
+                if self.entries[index].timestamp <= now {
+                    expired.push(index);
+                } else {
+                    // 高层级的降级，或者同一毫秒内还未到期
+                    self.schedule(index);
+                }
+            }
+            // 同一槽位内的条目按时间戳排序，时间戳相同的按插入顺序，链表头部最先弹出
+            expired.reverse();
+            expired.sort_by_key(|index| self.entries[*index].timestamp);
+            for index in expired.into_iter().rev() {
+                self.link(index, Location::Expired);
+            }
+            // 逐个槽位到期，以保证先到期的先弹出
+            if self.expired.is_some() || (0 == level && deadline == target) {
+                return;
+            }
+        }
+    }
+}
+
+impl<T: Debug> Debug for TimerWheel<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let entries: Vec<_> = self
+            .entries
+            .iter()
+            .filter_map(|entry| entry.value.as_ref().map(|value| (entry.timestamp, value)))
+            .collect();
+        f.debug_struct("TimerWheel")
+            .field("elapsed", &self.elapsed)
+            .field("entries", &entries)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pop_in_order() {
+        let start = 1_700_000_000_000_000_000;
+        let mut wheel = TimerWheel::new(start);
+        let delays = [
+            1,
+            TICK - 1,
+            5 * TICK,
+            70 * TICK,
+            5000 * TICK,
+            300_000 * TICK,
+            50_000_000 * TICK,
+        ];
+        for delay in delays.iter().rev() {
+            _ = wheel.insert(start + delay, *delay);
+        }
+        assert_eq!(delays.len(), wheel.len());
+        assert_eq!(None, wheel.pop_expired(start));
+        for delay in delays {
+            assert_eq!(None, wheel.pop_expired(start + delay - 1));
+            assert_eq!(
+                Some((start + delay, delay)),
+                wheel.pop_expired(start + delay)
+            );
+        }
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn test_pop_in_order_within_slot() {
+        let mut wheel = TimerWheel::new(0);
+        // 同一毫秒内，以及一次推进跨越的高层级槽位内
+        _ = wheel.insert(TICK - 1, 3);
+        _ = wheel.insert(1, 1);
+        _ = wheel.insert(TICK - 1, 4);
+        _ = wheel.insert(2, 2);
+        _ = wheel.insert(100 * TICK, 6);
+        _ = wheel.insert(70 * TICK, 5);
+        let popped: Vec<_> =
+            std::iter::from_fn(|| wheel.pop_expired(100 * TICK).map(|(_, v)| v)).collect();
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], popped);
+    }
+
+    #[test]
+    fn test_far_future() {
+        let mut wheel = TimerWheel::new(0);
+        _ = wheel.insert(u64::MAX, "never");
+        let timestamp = (MAX_TICKS + 10) * TICK;
+        _ = wheel.insert(timestamp, "far");
+        assert_eq!(None, wheel.pop_expired(MAX_TICKS * TICK));
+        assert_eq!(Some((timestamp, "far")), wheel.pop_expired(timestamp));
+        assert_eq!(None, wheel.pop_expired(u64::MAX - 1));
+        assert_eq!(1, wheel.len());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut wheel = TimerWheel::new(0);
+        let key1 = wheel.insert(10 * TICK, 1);
+        let key2 = wheel.insert(10 * TICK, 2);
+        _ = wheel.insert(100 * TICK, 3);
+        assert_eq!(Some(1), wheel.cancel(key1));
+        assert_eq!(None, wheel.cancel(key1));
+        // the vacant entry is reused, but the old key is invalid
+        let key4 = wheel.insert(20 * TICK, 4);
+        assert_eq!(None, wheel.cancel(key1));
+        assert_eq!(Some((10 * TICK, 2)), wheel.pop_expired(20 * TICK));
+        assert_eq!(None, wheel.cancel(key2));
+        assert_eq!(Some(4), wheel.cancel(key4));
+        assert_eq!(None, wheel.pop_expired(99 * TICK));
+        assert_eq!(vec![3], wheel.remove_if(|v| *v == 3));
+        assert!(wheel.is_empty());
+    }
+}
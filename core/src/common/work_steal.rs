@@ -60,6 +60,38 @@ impl<T: Debug> WorkStealQueue<T> {
             .store(self.len().saturating_add(1), Ordering::Release);
     }
 
+    /// Push elements to the global queue, the length is only updated once.
+    pub fn push_batch(&self, items: impl IntoIterator<Item = T>) {
+        let mut count = 0;
+        for item in items {
+            self.shared_queue.push(item);
+            count += 1;
+        }
+        //add count
+        self.len
+            .store(self.len().saturating_add(count), Ordering::Release);
+    }
+
+    /// Pop at most `max` elements from the global queue, the length is only updated once.
+    pub fn pop_batch(&self, max: usize) -> Vec<T> {
+        let mut batch = Vec::new();
+        // Fast path, if len == 0, then there are no values
+        if self.is_empty() {
+            return batch;
+        }
+        while batch.len() < max {
+            match self.shared_queue.steal() {
+                Steal::Success(item) => batch.push(item),
+                Steal::Retry => {}
+                Steal::Empty => break,
+            }
+        }
+        // Decrement the count.
+        self.len
+            .store(self.len().saturating_sub(batch.len()), Ordering::Release);
+        batch
+    }
+
     /// Pop an element from the global queue.
     pub fn pop(&self) -> Option<T> {
         // Fast path, if len == 0, then there are no values
@@ -224,6 +256,42 @@ impl<'l, T: Debug> LocalQueue<'l, T> {
         }
     }
 
+    /// Push elements to the local queue at once, if the local queue is full, first push
+    /// half to global, then push the rest to global.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use open_coroutine_core::common::work_steal::WorkStealQueue;
+    ///
+    /// let queue = WorkStealQueue::new(1, 4);
+    /// let local = queue.local_queue();
+    /// local.push_batch(0..6);
+    /// assert_eq!(local.len(), 2);
+    /// assert_eq!(queue.len(), 4);
+    /// assert_eq!(local.pop_batch(8), vec![2, 3, 0, 1, 4, 5]);
+    /// assert!(queue.is_empty());
+    /// ```
+    pub fn push_batch(&self, items: impl IntoIterator<Item = T>) {
+        let mut items = items.into_iter();
+        self.queue
+            .extend(items.by_ref().take(self.queue.spare_capacity()));
+        let mut rest = items.peekable();
+        if rest.peek().is_some() {
+            //把本地队列的一半放到全局队列
+            let count = self.len() / 2;
+            self.shared
+                .push_batch((0..count).map_while(|_| self.queue.pop()));
+            //直接放到全局队列
+            self.shared.push_batch(rest);
+        }
+    }
+
+    /// Pop at most `max` elements, see [`LocalQueue::pop`].
+    pub fn pop_batch(&self, max: usize) -> Vec<T> {
+        (0..max).map_while(|_| self.pop()).collect()
+    }
+
     /// Increment the tick
     fn tick(&self) -> u32 {
         let val = self.tick.fetch_add(1, Ordering::Release);
@@ -330,8 +398,11 @@ impl<'l, T: Debug> LocalQueue<'l, T> {
             }
             self.release_lock();
         }
-        //都steal不到，只好从shared里pop
-        self.shared.pop()
+        //都steal不到，只好从shared里批量pop，多余的放入本地队列
+        let mut batch = self.shared.pop_batch(self.max_steal().max(1)).into_iter();
+        let item = batch.next();
+        self.queue.extend(batch);
+        item
     }
 }
 
@@ -1,4 +1,6 @@
+use crate::co_pool::RejectPolicy;
 use crate::common::constants::{cpu_count, DEFAULT_STACK_SIZE};
+use crate::scheduler::ReadyQueueKind;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
@@ -11,6 +13,12 @@ pub struct Config {
     min_memory_count: usize,
     memory_keep_alive_time: u64,
     hook: bool,
+    stack_canary: bool,
+    ready_queue: ReadyQueueKind,
+    aging_threshold: u64,
+    task_capacity: usize,
+    reject_policy: RejectPolicy,
+    result_ttl: u64,
 }
 
 impl Config {
@@ -40,6 +48,12 @@ impl Config {
             min_memory_count,
             memory_keep_alive_time,
             hook,
+            stack_canary: false,
+            ready_queue: ReadyQueueKind::default(),
+            aging_threshold: 0,
+            task_capacity: usize::MAX,
+            reject_policy: RejectPolicy::default(),
+            result_ttl: u64::MAX,
         }
     }
 
@@ -83,6 +97,36 @@ impl Config {
         self.hook
     }
 
+    #[must_use]
+    pub fn stack_canary(&self) -> bool {
+        self.stack_canary
+    }
+
+    #[must_use]
+    pub fn ready_queue(&self) -> ReadyQueueKind {
+        self.ready_queue
+    }
+
+    #[must_use]
+    pub fn aging_threshold(&self) -> u64 {
+        self.aging_threshold
+    }
+
+    #[must_use]
+    pub fn task_capacity(&self) -> usize {
+        self.task_capacity
+    }
+
+    #[must_use]
+    pub fn reject_policy(&self) -> RejectPolicy {
+        self.reject_policy
+    }
+
+    #[must_use]
+    pub fn result_ttl(&self) -> u64 {
+        self.result_ttl
+    }
+
     pub fn set_event_loop_size(&mut self, event_loop_size: usize) -> &mut Self {
         assert!(
             event_loop_size > 0,
@@ -132,6 +176,36 @@ impl Config {
         self.hook = hook;
         self
     }
+
+    pub fn set_stack_canary(&mut self, stack_canary: bool) -> &mut Self {
+        self.stack_canary = stack_canary;
+        self
+    }
+
+    pub fn set_ready_queue(&mut self, ready_queue: ReadyQueueKind) -> &mut Self {
+        self.ready_queue = ready_queue;
+        self
+    }
+
+    pub fn set_aging_threshold(&mut self, aging_threshold: u64) -> &mut Self {
+        self.aging_threshold = aging_threshold;
+        self
+    }
+
+    pub fn set_task_capacity(&mut self, task_capacity: usize) -> &mut Self {
+        self.task_capacity = task_capacity;
+        self
+    }
+
+    pub fn set_reject_policy(&mut self, reject_policy: RejectPolicy) -> &mut Self {
+        self.reject_policy = reject_policy;
+        self
+    }
+
+    pub fn set_result_ttl(&mut self, result_ttl: u64) -> &mut Self {
+        self.result_ttl = result_ttl;
+        self
+    }
 }
 
 impl Default for Config {
@@ -0,0 +1,149 @@
+use crate::common::constants::CoroutineState;
+use crate::common::join_error::JoinError;
+use crate::coroutine::suspender::Suspender;
+use crate::coroutine::Coroutine;
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+use std::iter::FusedIterator;
+use std::rc::Rc;
+
+/// The values passed between the generator and its coroutine.
+struct Slot<Yield, Return> {
+    yielded: RefCell<Option<Yield>>,
+    returned: RefCell<Option<Return>>,
+}
+
+/// The yielder used by the generator closure to yield values.
+pub struct Yielder<'y, Yield, Return> {
+    suspender: &'y Suspender<'y, (), ()>,
+    slot: &'y Slot<Yield, Return>,
+}
+
+impl<Yield, Return> Yielder<'_, Yield, Return> {
+    /// Yield a value to the consumer of the generator, and suspend until the next value
+    /// is requested.
+    pub fn suspend_with(&self, value: Yield) {
+        _ = self.slot.yielded.borrow_mut().replace(value);
+        self.suspender.suspend();
+    }
+}
+
+impl<Yield, Return> Debug for Yielder<'_, Yield, Return> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Yielder").finish_non_exhaustive()
+    }
+}
+
+/// A stackful generator built on [`Coroutine`], the yielded values are produced by
+/// [`Iterator::next`], and they don't need to be `Copy` or `Eq`.
+///
+/// It runs without the scheduler, and the trap handler is still active, so a stack overflow
+/// in the generator will end it with a [`JoinError`] instead of crashing the process.
+///
+/// Dropping a generator which has not finished unwinds it from its suspension point, so the
+/// values owned by the generator are dropped.
+pub struct Generator<'g, Yield, Return> {
+    inner: Coroutine<'g, (), (), ()>,
+    slot: Rc<Slot<Yield, Return>>,
+}
+
+impl<Yield: 'static, Return: 'static> Generator<'_, Yield, Return> {
+    /// Create a new generator.
+    ///
+    /// # Errors
+    /// if stack allocate failed.
+    pub fn new<F>(name: Option<String>, f: F, stack_size: Option<usize>) -> std::io::Result<Self>
+    where
+        F: FnOnce(&Yielder<Yield, Return>) -> Return + 'static,
+    {
+        let slot = Rc::new(Slot {
+            yielded: RefCell::new(None),
+            returned: RefCell::new(None),
+        });
+        let inner_slot = slot.clone();
+        let inner = Coroutine::new(
+            name,
+            move |suspender, ()| {
+                let yielder = Yielder {
+                    suspender,
+                    slot: &inner_slot,
+                };
+                let returned = f(&yielder);
+                _ = inner_slot.returned.borrow_mut().replace(returned);
+            },
+            stack_size,
+            None,
+        )?;
+        Ok(Generator { inner, slot })
+    }
+}
+
+impl<Yield, Return> Generator<'_, Yield, Return> {
+    /// Get the name of this generator.
+    pub fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    /// Returns `true` if the generator has returned or failed.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.inner.state(),
+            CoroutineState::Complete(()) | CoroutineState::Error(_) | CoroutineState::Cancelled
+        )
+    }
+
+    /// Consumes this generator and returns its final value.
+    ///
+    /// Returns `None` if the generator has not finished yet, the error tells why
+    /// the generator failed, such as panic or stack overflow.
+    pub fn into_return(self) -> Option<Result<Return, JoinError>> {
+        if let Some(error) = self.inner.take_error() {
+            return Some(Err(error));
+        }
+        self.slot.returned.borrow_mut().take().map(Ok)
+    }
+}
+
+impl<Yield: 'static, Return: 'static> Iterator for Generator<'_, Yield, Return> {
+    type Item = Yield;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        //被抢占时没有产生值，需要继续执行
+        while let Ok(CoroutineState::Suspend((), _)) = self.inner.resume() {
+            if let Some(value) = self.slot.yielded.borrow_mut().take() {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+impl<Yield: 'static, Return: 'static> FusedIterator for Generator<'_, Yield, Return> {}
+
+impl<Yield, Return> Drop for Generator<'_, Yield, Return> {
+    fn drop(&mut self) {
+        if !matches!(self.inner.state(), CoroutineState::Suspend((), _)) {
+            return;
+        }
+        //未消费完的生成器在挂起点展开，释放其栈上的值
+        Suspender::<(), ()>::request_cancel(true);
+        let mut state = self.inner.resume();
+        Suspender::<(), ()>::request_cancel(false);
+        //展开时可能被抢占，但不再继续产生值
+        while let Ok(CoroutineState::Suspend((), _)) = state {
+            if self.slot.yielded.borrow_mut().take().is_some() {
+                break;
+            }
+            state = self.inner.resume();
+        }
+    }
+}
+
+impl<Yield, Return> Debug for Generator<'_, Yield, Return> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Generator")
+            .field("name", &self.name())
+            .field("state", &self.inner.state())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,98 @@
+use crate::impl_display_by_debug;
+use once_cell::sync::Lazy;
+use std::sync::{Mutex, PoisonError};
+
+/// The generations of all slots, the slot is in use if its generation is odd.
+static SLAB: Lazy<Mutex<IdSlab>> = Lazy::new(Mutex::default);
+
+#[derive(Debug, Default)]
+struct IdSlab {
+    generations: Vec<u32>,
+    vacant: Vec<u32>,
+}
+
+/// A compact coroutine ID which is made of a slab index and a generation, the index will be
+/// reused after the coroutine dropped, but the generation makes the old IDs never match again.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct CoroutineId {
+    index: u32,
+    generation: u32,
+}
+
+impl CoroutineId {
+    /// Allocate a new ID.
+    pub(crate) fn allocate() -> Self {
+        let mut slab = SLAB.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = slab.vacant.pop().unwrap_or_else(|| {
+            slab.generations.push(0);
+            u32::try_from(slab.generations.len() - 1).expect("too many coroutines")
+        });
+        let generation = &mut slab.generations[index as usize];
+        *generation = generation.wrapping_add(1);
+        CoroutineId {
+            index,
+            generation: *generation,
+        }
+    }
+
+    /// Release this ID, then its index can be reused.
+    pub(crate) fn release(self) {
+        let mut slab = SLAB.lock().unwrap_or_else(PoisonError::into_inner);
+        let generation = &mut slab.generations[self.index as usize];
+        if *generation == self.generation {
+            *generation = generation.wrapping_add(1);
+            slab.vacant.push(self.index);
+        }
+    }
+
+    /// Returns the slab index of this ID.
+    #[must_use]
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Returns the generation of this ID.
+    #[must_use]
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl_display_by_debug!(CoroutineId);
+
+impl From<CoroutineId> for u64 {
+    fn from(id: CoroutineId) -> Self {
+        (u64::from(id.index) << 32) | u64::from(id.generation)
+    }
+}
+
+impl From<u64> for CoroutineId {
+    #[allow(clippy::cast_possible_truncation)]
+    fn from(value: u64) -> Self {
+        CoroutineId {
+            index: (value >> 32) as u32,
+            generation: value as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reuse() {
+        let id = CoroutineId::allocate();
+        assert_eq!(id, CoroutineId::from(u64::from(id)));
+        id.release();
+        // releasing twice is ignored
+        id.release();
+        let new = CoroutineId::allocate();
+        let other = CoroutineId::allocate();
+        assert_ne!(id, new);
+        assert_ne!(new, other);
+        new.release();
+        other.release();
+    }
+}
@@ -1,12 +1,16 @@
 use crate::common::constants::CoroutineState;
+use crate::common::join_error::{JoinError, JoinErrorKind};
+use crate::coroutine::id::CoroutineId;
 use crate::coroutine::listener::Listener;
 use crate::coroutine::local::CoroutineLocal;
+use crate::coroutine::stack_pool::{PooledStack, StackPool};
 use crate::coroutine::suspender::Suspender;
 use crate::coroutine::StackInfo;
 use crate::{catch, warn};
-use corosensei::stack::{DefaultStack, Stack};
+use corosensei::stack::Stack;
 use corosensei::trap::TrapHandlerRegs;
 use corosensei::CoroutineResult;
+use std::any::Any;
 use std::cell::{Cell, RefCell, UnsafeCell};
 use std::collections::VecDeque;
 use std::ffi::c_longlong;
@@ -25,13 +29,21 @@ cfg_if::cfg_if! {
 /// Use `corosensei` as the low-level coroutine.
 #[repr(C)]
 pub struct Coroutine<'c, Param, Yield, Return> {
+    pub(crate) id: CoroutineId,
     pub(crate) name: String,
-    inner: corosensei::Coroutine<Param, Yield, Result<Return, &'static str>, DefaultStack>,
+    inner: corosensei::Coroutine<Param, Yield, Result<Return, JoinError>, PooledStack>,
     pub(crate) state: Cell<CoroutineState<Yield, Return>>,
     stack_infos: UnsafeCell<VecDeque<StackInfo>>,
     pub(crate) listeners: VecDeque<&'c dyn Listener<Yield, Return>>,
     pub(crate) local: CoroutineLocal<'c>,
     pub(crate) priority: Option<c_longlong>,
+    stack_canary: bool,
+    //扩容栈的峰值使用量，包含其下方所有栈的大小
+    grown_peak: Cell<usize>,
+    pub(crate) stack_peak: Cell<Option<usize>>,
+    pub(crate) error: Cell<Option<JoinError>>,
+    //类型化的返回值，随Complete状态一起交给调度器
+    pub(crate) result: Cell<Option<Box<dyn Any + Send>>>,
 }
 
 impl<'c, Param, Yield, Return> Coroutine<'c, Param, Yield, Return> {
@@ -86,11 +98,11 @@ impl<'c, Param, Yield, Return> Coroutine<'c, Param, Yield, Return> {
                     if let Some(co) = Self::current() {
                         let stack_ptr_in_bounds = co.stack_ptr_in_bounds(sp);
                         let regs = co.inner.trap_handler().setup_trap_handler(move || {
-                            Err(if stack_ptr_in_bounds {
-                                "invalid memory reference"
+                            Err(JoinError::new(if stack_ptr_in_bounds {
+                                JoinErrorKind::InvalidMemory
                             } else {
-                                "stack overflow"
-                            })
+                                JoinErrorKind::StackOverflow
+                            }))
                         });
                         cfg_if::cfg_if! {
                             if #[cfg(all(
@@ -217,11 +229,11 @@ impl<'c, Param, Yield, Return> Coroutine<'c, Param, Yield, Return> {
 
                     let stack_ptr_in_bounds = co.stack_ptr_in_bounds(sp);
                     let regs = co.inner.trap_handler().setup_trap_handler(move || {
-                        Err(if stack_ptr_in_bounds {
-                            "invalid memory reference"
+                        Err(JoinError::new(if stack_ptr_in_bounds {
+                            JoinErrorKind::InvalidMemory
                         } else {
-                            "stack overflow"
-                        })
+                            JoinErrorKind::StackOverflow
+                        }))
                     });
 
                     cfg_if::cfg_if! {
@@ -287,6 +299,17 @@ impl<'c, Param, Yield, Return> Coroutine<'c, Param, Yield, Return> {
     }
 
     pub(crate) fn add_raw_listener(&mut self, listener: &'c dyn Listener<Yield, Return>) {
+        //协程还未开始执行，对新加入的监听器来说相当于刚创建
+        if !self.inner.started() {
+            let stack_size = self
+                .stack_infos_ref()
+                .front()
+                .map_or(0, |info| info.stack_top - info.stack_bottom);
+            _ = catch!(
+                || listener.on_create(self, self.name(), stack_size),
+                format!("{} invoke on_create", self.name())
+            );
+        }
         self.listeners.push_back(listener);
     }
 
@@ -331,10 +354,22 @@ impl<'c, Param, Yield, Return> Coroutine<'c, Param, Yield, Return> {
             if remaining_stack >= red_zone {
                 return Ok(callback());
             }
-            return DefaultStack::new(stack_size).map(|stack| {
+            return StackPool::allocate(stack_size).map(|mut stack| {
                 co.stack_infos_mut().push_back(StackInfo::from(&stack));
-                let r = corosensei::on_stack(stack, callback);
-                _ = co.stack_infos_mut().pop_back();
+                let r = corosensei::on_stack(&mut stack, callback);
+                if let Some(info) = co.stack_infos_mut().pop_back() {
+                    if stack.canary() {
+                        let below: usize = co
+                            .stack_infos_ref()
+                            .iter()
+                            .map(|info| info.stack_top - info.stack_bottom)
+                            .sum();
+                        let peak = below + StackPool::peak_usage(&info);
+                        if peak > co.grown_peak.get() {
+                            co.grown_peak.set(peak);
+                        }
+                    }
+                }
                 r
             });
         }
@@ -351,7 +386,7 @@ impl<'c, Param, Yield, Return> Coroutine<'c, Param, Yield, Return> {
                 return Ok(callback());
             }
         }
-        DefaultStack::new(stack_size).map(|stack| {
+        StackPool::allocate(stack_size).map(|stack| {
             STACK_INFOS.with(|s| {
                 s.borrow_mut().push_back(StackInfo::from(&stack));
             });
@@ -369,6 +404,14 @@ impl<Param, Yield, Return> Drop for Coroutine<'_, Param, Yield, Return> {
             unsafe { self.inner.force_reset() };
             warn!("Coroutine {} is dropped without complete", self.name());
         }
+        for listener in &self.listeners {
+            _ = catch!(
+                || listener.on_drop(self, self.name()),
+                format!("{} invoke on_drop", self.name())
+            );
+        }
+        crate::dump::CoroutineDump::remove(self.name());
+        self.id.release();
     }
 }
 
@@ -391,16 +434,15 @@ where
     where
         F: FnOnce(&Suspender<Param, Yield>, Param) -> Return + 'static,
     {
-        let stack_size = stack_size
-            .unwrap_or(crate::common::constants::DEFAULT_STACK_SIZE)
-            .max(crate::common::page_size());
-        let stack = DefaultStack::new(stack_size)?;
+        let stack_size = stack_size.unwrap_or(crate::common::constants::DEFAULT_STACK_SIZE);
+        let stack = StackPool::allocate(stack_size)?;
+        let stack_canary = stack.canary();
         let stack_infos = UnsafeCell::new(VecDeque::from([StackInfo {
             stack_top: stack.base().get(),
             stack_bottom: stack.limit().get(),
         }]));
         let name = name.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
-        let co_name = name.clone().leak();
+        let co_name = name.clone();
         let inner = corosensei::Coroutine::with_stack(stack, move |y, p| {
             catch!(
                 move || {
@@ -410,12 +452,12 @@ where
                     Suspender::<Param, Yield>::clean_current();
                     r
                 },
-                format!("coroutine {co_name} failed without message"),
                 co_name
             )
         });
         #[allow(unused_mut)]
         let mut co = Coroutine {
+            id: CoroutineId::allocate(),
             name,
             inner,
             stack_infos,
@@ -423,6 +465,11 @@ where
             listeners: VecDeque::default(),
             local: CoroutineLocal::default(),
             priority,
+            stack_canary,
+            grown_peak: Cell::new(0),
+            stack_peak: Cell::new(None),
+            error: Cell::new(None),
+            result: Cell::new(None),
         };
         cfg_if::cfg_if! {
             if #[cfg(all(unix, feature = "preemptive"))] {
@@ -459,19 +506,43 @@ where
                 }
             }
             CoroutineResult::Return(result) => {
+                self.measure_stack_peak();
                 if let Ok(returns) = result {
                     self.complete(returns)?;
                     Ok(CoroutineState::Complete(returns))
+                } else if Suspender::<Yield, Param>::take_cancelled() {
+                    self.cancel()?;
+                    Ok(CoroutineState::Cancelled)
                 } else {
-                    let message = result.unwrap_err();
-                    self.error(message)?;
-                    Ok(CoroutineState::Error(message))
+                    let error = result.unwrap_err();
+                    let reason = error.reason();
+                    self.error(error)?;
+                    Ok(CoroutineState::Error(reason))
                 }
             }
         }
     }
 }
 
+impl<Param, Yield, Return> Coroutine<'_, Param, Yield, Return>
+where
+    Yield: Debug + Copy,
+    Return: Debug + Copy,
+{
+    /// Measure the peak stack usage before the stack is released.
+    fn measure_stack_peak(&self) {
+        if !self.stack_canary {
+            return;
+        }
+        if let Some(info) = self.stack_infos_ref().front() {
+            let peak = StackPool::peak_usage(info).max(self.grown_peak.get());
+            self.stack_peak.set(Some(peak));
+            let stack_size = info.stack_top - info.stack_bottom - crate::common::page_size();
+            self.on_stack_usage(self, stack_size, peak);
+        }
+    }
+}
+
 impl<S: Stack> From<&S> for StackInfo {
     fn from(stack: &S) -> Self {
         Self {
@@ -6,6 +6,11 @@ use std::fmt::Debug;
 /// A trait mainly used for monitors.
 #[allow(unused_variables)]
 pub trait Listener<Yield, Return>: Debug {
+    /// Callback when the coroutine is created, it's fired when the listener is added to
+    /// a coroutine which has not been resumed yet, such as in `Coroutine::new` and
+    /// `Scheduler::submit_raw_co`.
+    fn on_create(&self, local: &CoroutineLocal, name: &str, stack_size: usize) {}
+
     /// Callback after changing the status of coroutine.
     fn on_state_changed(
         &self,
@@ -44,6 +49,14 @@ pub trait Listener<Yield, Return>: Debug {
         message: &str,
     ) {
     }
+
+    /// Callback when the coroutine is finished and its peak stack usage is measured,
+    /// only works when the stack canary is enabled.
+    fn on_stack_usage(&self, local: &CoroutineLocal, stack_size: usize, peak: usize) {}
+
+    /// Callback when the coroutine is dropped, then its stack will be freed.
+    /// It's also fired when an unfinished coroutine is dropped.
+    fn on_drop(&self, local: &CoroutineLocal, name: &str) {}
 }
 
 macro_rules! broadcast {
@@ -52,7 +65,6 @@ macro_rules! broadcast {
             for listener in &self.listeners {
                 _ = $crate::catch!(
                     || listener.$impl_method_name($($arg, )*),
-                    format!("Listener {} failed without message", $method_name),
                     format!("{} invoke {}", self.name(), $method_name)
                 );
             }
@@ -102,4 +114,10 @@ where
         old_state: CoroutineState<Yield, Return>,
         message: &str
     ), "on_error");
+
+    broadcast!(on_stack_usage(
+        local: &CoroutineLocal,
+        stack_size: usize,
+        peak: usize
+    ), "on_stack_usage");
 }
@@ -1,48 +1,372 @@
-use crate::impl_display_by_debug;
+use crate::{impl_current_for, impl_display_by_debug};
 use dashmap::DashMap;
-use std::ffi::c_void;
-use std::fmt::Debug;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt::{Debug, Formatter};
+
+/// How to inherit a value into the child task.
+#[derive(Debug, Copy, Clone)]
+struct Inherit {
+    name: &'static str,
+    clone: fn(&dyn Any) -> Box<dyn Any + Send>,
+}
+
+struct LocalValue {
+    value: Box<dyn Any + Send>,
+    inherit: Option<Inherit>,
+}
+
+impl LocalValue {
+    fn new(value: Box<dyn Any + Send>) -> Self {
+        LocalValue {
+            value,
+            inherit: None,
+        }
+    }
+}
+
+/// The inheritable coroutine locals captured from the parent coroutine,
+/// they will be installed into the child task.
+#[derive(Default)]
+pub struct InheritableLocals(Vec<(Inherit, Box<dyn Any + Send>)>);
+
+impl Debug for InheritableLocals {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|(inherit, _)| inherit.name))
+            .finish()
+    }
+}
+
+impl InheritableLocals {
+    /// Capture the inheritable locals of the current coroutine.
+    #[must_use]
+    pub fn capture() -> Self {
+        CoroutineLocal::current()
+            .map(CoroutineLocal::inheritable)
+            .unwrap_or_default()
+    }
+
+    /// Returns `true` if nothing is captured.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
 
-/// todo provide macro like [`std::thread_local`]
 /// A struct for coroutines handles local args.
+///
+/// The values are type checked when reading, and dropped when the coroutine finishes.
+/// See [`crate::coroutine_local`] for the statically typed keys.
 #[repr(C)]
-#[derive(Debug, Default)]
-pub struct CoroutineLocal<'c>(DashMap<&'c str, usize>);
+#[derive(Default)]
+pub struct CoroutineLocal<'c>(DashMap<&'c str, LocalValue>);
 
 #[allow(clippy::must_use_candidate)]
 impl<'c> CoroutineLocal<'c> {
-    /// Put a value into the coroutine local.
-    pub fn put<V>(&self, key: &'c str, val: V) -> Option<V> {
-        let v = Box::leak(Box::new(val));
+    /// Put a value into the coroutine local, the value must be [`Send`] because the
+    /// coroutine may be resumed by another thread.
+    ///
+    /// Returns the previous value if it has the same type.
+    pub fn put<V: Send + 'static>(&self, key: &'c str, val: V) -> Option<V> {
         self.0
-            .insert(key, std::ptr::from_mut(v) as usize)
-            .map(|ptr| unsafe { *Box::from_raw((ptr as *mut c_void).cast::<V>()) })
+            .insert(key, LocalValue::new(Box::new(val)))
+            .and_then(|v| v.value.downcast::<V>().ok())
+            .map(|v| *v)
     }
 
     /// Get a value ref from the coroutine local.
-    pub fn get<V>(&self, key: &'c str) -> Option<&V> {
+    ///
+    /// Returns `None` if the key does not exist or the value has a different type.
+    pub fn get<V: 'static>(&self, key: &'c str) -> Option<&V> {
         self.0
             .get(key)
-            .map(|ptr| unsafe { &*(*ptr as *mut c_void).cast::<V>() })
+            .and_then(|v| v.value.downcast_ref::<V>().map(std::ptr::from_ref))
+            .map(|ptr| unsafe { &*ptr })
     }
 
     /// Get a mut value ref from the coroutine local.
-    pub fn get_mut<V>(&self, key: &'c str) -> Option<&mut V> {
+    ///
+    /// Returns `None` if the key does not exist or the value has a different type.
+    #[allow(clippy::mut_from_ref)]
+    pub fn get_mut<V: 'static>(&self, key: &'c str) -> Option<&mut V> {
         self.0
-            .get(key)
-            .map(|ptr| unsafe { &mut *(*ptr as *mut c_void).cast::<V>() })
+            .get_mut(key)
+            .and_then(|mut v| v.value.downcast_mut::<V>().map(std::ptr::from_mut))
+            .map(|ptr| unsafe { &mut *ptr })
     }
 
     /// Remove a key from the coroutine local.
-    pub fn remove<V>(&self, key: &'c str) -> Option<V> {
+    ///
+    /// Returns `None` if the key does not exist or the value has a different type.
+    pub fn remove<V: 'static>(&self, key: &'c str) -> Option<V> {
         self.0
             .remove(key)
-            .map(|ptr| unsafe { *Box::from_raw((ptr.1 as *mut c_void).cast::<V>()) })
+            .and_then(|(_, v)| v.value.downcast::<V>().ok())
+            .map(|v| *v)
+    }
+
+    /// Drop all values in the coroutine local.
+    pub(crate) fn clear(&self) {
+        while !self.0.is_empty() {
+            self.remove_all(self.0.iter().map(|entry| *entry.key()).collect());
+        }
+    }
+
+    fn remove_all(&self, keys: Vec<&'c str>) {
+        // drop the values outside the lock, they may access the coroutine local again
+        for key in keys {
+            drop(self.0.remove(key));
+        }
+    }
+
+    fn inheritable(&self) -> InheritableLocals {
+        InheritableLocals(
+            self.0
+                .iter()
+                .filter_map(|entry| {
+                    entry
+                        .inherit
+                        .map(|inherit| (inherit, (inherit.clone)(entry.value.as_ref())))
+                })
+                .collect(),
+        )
+    }
+
+    /// Run `f` with the inherited locals in the current coroutine, then drop the inherited
+    /// locals and the locals created by `f`, the shadowed locals will be restored.
+    pub(crate) fn scoped<R>(inherited: InheritableLocals, f: impl FnOnce() -> R) -> R {
+        let Some(local) = Self::current() else {
+            return f();
+        };
+        let before: HashSet<&'c str> = local.0.iter().map(|entry| *entry.key()).collect();
+        let mut installed = Vec::new();
+        let mut shadowed = Vec::new();
+        for (inherit, value) in inherited.0 {
+            installed.push(inherit.name);
+            if let Some(old) = local.0.insert(
+                inherit.name,
+                LocalValue {
+                    value,
+                    inherit: Some(inherit),
+                },
+            ) {
+                shadowed.push((inherit.name, old));
+            }
+        }
+        let r = f();
+        //协程挂起期间可能被移动，需要重新获取
+        let local = Self::current().expect("current coroutine local not found");
+        local.remove_all(
+            local
+                .0
+                .iter()
+                .map(|entry| *entry.key())
+                .filter(|key| !before.contains(key) || installed.contains(key))
+                .collect(),
+        );
+        for (key, value) in shadowed {
+            drop(local.0.insert(key, value));
+        }
+        r
+    }
+}
+
+impl Debug for CoroutineLocal<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_set()
+            .entries(self.0.iter().map(|entry| *entry.key()))
+            .finish()
     }
 }
 
 impl_display_by_debug!(CoroutineLocal<'c>);
 
+impl_current_for!(COROUTINE_LOCAL, CoroutineLocal<'c>);
+
+/// A coroutine local storage key which owns its contents, see [`crate::coroutine_local`].
+///
+/// When used outside a coroutine, it behaves like [`std::thread::LocalKey`]. The value must be
+/// [`Send`] because the coroutine may be resumed by another thread.
+pub struct LocalKey<T: Send + 'static> {
+    name: &'static str,
+    init: fn() -> T,
+    thread_local: &'static std::thread::LocalKey<RefCell<Option<T>>>,
+    inherit: Option<Inherit>,
+}
+
+impl<T: Send + 'static> Debug for LocalKey<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalKey")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Send + 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const fn new(
+        name: &'static str,
+        init: fn() -> T,
+        thread_local: &'static std::thread::LocalKey<RefCell<Option<T>>>,
+    ) -> Self {
+        LocalKey {
+            name,
+            init,
+            thread_local,
+            inherit: None,
+        }
+    }
+
+    #[doc(hidden)]
+    pub const fn new_inheritable(
+        name: &'static str,
+        init: fn() -> T,
+        thread_local: &'static std::thread::LocalKey<RefCell<Option<T>>>,
+    ) -> Self
+    where
+        T: Clone,
+    {
+        LocalKey {
+            name,
+            init,
+            thread_local,
+            inherit: Some(Inherit {
+                name,
+                clone: Self::clone_value,
+            }),
+        }
+    }
+
+    fn clone_value(value: &dyn Any) -> Box<dyn Any + Send>
+    where
+        T: Clone,
+    {
+        let cell = value
+            .downcast_ref::<RefCell<Option<T>>>()
+            .expect("coroutine local type mismatch");
+        Box::new(RefCell::new(cell.borrow().clone()))
+    }
+
+    fn with_cell<R>(&'static self, f: impl FnOnce(&RefCell<Option<T>>) -> R) -> R {
+        if let Some(local) = CoroutineLocal::current() {
+            let cell = std::ptr::from_ref(
+                local
+                    .0
+                    .entry(self.name)
+                    .or_insert_with(|| LocalValue {
+                        value: Box::new(RefCell::new(None::<T>)),
+                        inherit: self.inherit,
+                    })
+                    .value
+                    .downcast_ref::<RefCell<Option<T>>>()
+                    .unwrap_or_else(|| panic!("coroutine local {} type mismatch", self.name)),
+            );
+            // the value will not be removed until the coroutine finishes
+            return f(unsafe { &*cell });
+        }
+        self.thread_local.with(f)
+    }
+
+    /// Acquires a reference to the value in this coroutine local storage key.
+    /// The value will be lazily initialized if this is the first time accessed.
+    ///
+    /// # Panics
+    /// if the value is mutably borrowed, such as calling `set` in `f`.
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        self.with_cell(|cell| {
+            if cell.borrow().is_none() {
+                _ = cell.borrow_mut().replace((self.init)());
+            }
+            f(cell.borrow().as_ref().expect("coroutine local not init"))
+        })
+    }
+
+    /// Sets or initializes the contained value.
+    ///
+    /// # Panics
+    /// if the value is borrowed, such as calling `set` in `with`.
+    pub fn set(&'static self, value: T) {
+        self.with_cell(|cell| drop(cell.borrow_mut().replace(value)));
+    }
+
+    /// Takes the contained value, the key will be lazily initialized again when accessed.
+    ///
+    /// # Panics
+    /// if the value is borrowed, such as calling `take` in `with`.
+    #[must_use]
+    pub fn take(&'static self) -> Option<T> {
+        self.with_cell(|cell| cell.borrow_mut().take())
+    }
+}
+
+/// Declare a new coroutine local storage key of type [`LocalKey`].
+///
+/// The values are owned by the running coroutine and dropped when it completes, outside
+/// a coroutine they are owned by the current thread.
+///
+/// The keys declared with `inheritable` will be cloned into the child tasks, which are
+/// submitted from inside a coroutine, use `Arc` to share the value instead of cloning.
+/// Note that the `open-coroutine-hook` dylib has its own copy of this crate, so the keys are
+/// only visible to the coroutines scheduled by the same copy.
+///
+/// # Examples
+///
+/// ```
+/// open_coroutine_core::coroutine_local! {
+///     static REQUEST_ID: String = String::from("none");
+/// }
+///
+/// REQUEST_ID.set(String::from("1"));
+/// REQUEST_ID.with(|id| assert_eq!("1", id));
+/// assert_eq!(Some(String::from("1")), REQUEST_ID.take());
+/// ```
+#[macro_export]
+macro_rules! coroutine_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis inheritable static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $crate::coroutine_local!($(#[$attr])* $vis inheritable static $name: $t = $init);
+        $crate::coroutine_local!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis inheritable static $name:ident: $t:ty = $init:expr) => {
+        $crate::coroutine_local!(@key $(#[$attr])* $vis $name, $t, $init, new_inheritable);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $crate::coroutine_local!($(#[$attr])* $vis static $name: $t = $init);
+        $crate::coroutine_local!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $crate::coroutine_local!(@key $(#[$attr])* $vis $name, $t, $init, new);
+    };
+    (@key $(#[$attr:meta])* $vis:vis $name:ident, $t:ty, $init:expr, $new:ident) => {
+        $(#[$attr])*
+        $vis static $name: $crate::coroutine::local::LocalKey<$t> = {
+            std::thread_local! {
+                static THREAD_LOCAL: std::cell::RefCell<Option<$t>> =
+                    const { std::cell::RefCell::new(None) };
+            }
+            fn init() -> $t {
+                $init
+            }
+            //同一模块不同作用域中的同名key需要区分
+            $crate::coroutine::local::LocalKey::$new(
+                concat!(
+                    module_path!(),
+                    "::",
+                    stringify!($name),
+                    "@",
+                    line!(),
+                    ":",
+                    column!()
+                ),
+                init,
+                &THREAD_LOCAL,
+            )
+        };
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -53,7 +377,42 @@ mod tests {
         assert!(local.put("1", 1).is_none());
         assert_eq!(Some(1), local.put("1", 2));
         assert_eq!(2, *local.get("1").unwrap());
+        assert!(local.get::<String>("1").is_none());
         *local.get_mut("1").unwrap() = 3;
         assert_eq!(Some(3), local.remove("1"));
     }
+
+    crate::coroutine_local! {
+        static COUNTER: usize = 1;
+    }
+
+    #[test]
+    fn test_thread_local_fallback() {
+        COUNTER.with(|v| assert_eq!(1, *v));
+        COUNTER.set(2);
+        COUNTER.with(|v| assert_eq!(2, *v));
+        assert_eq!(Some(2), COUNTER.take());
+        COUNTER.with(|v| assert_eq!(1, *v));
+    }
+
+    #[test]
+    fn test_same_name_in_scopes() {
+        fn first() -> usize {
+            crate::coroutine_local! {
+                static KEY: usize = 1;
+            }
+            KEY.with(|v| *v)
+        }
+        fn second() -> String {
+            crate::coroutine_local! {
+                static KEY: String = String::from("2");
+            }
+            KEY.with(Clone::clone)
+        }
+        let local = CoroutineLocal::default();
+        CoroutineLocal::init_current(&local);
+        assert_eq!(1, first());
+        assert_eq!("2", second());
+        CoroutineLocal::clean_current();
+    }
 }
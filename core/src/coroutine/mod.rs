@@ -1,8 +1,11 @@
 use crate::common::constants::CoroutineState;
+use crate::common::join_error::JoinError;
 use crate::common::ordered_work_steal::Ordered;
+use crate::coroutine::id::CoroutineId;
 use crate::coroutine::listener::Listener;
 use crate::coroutine::local::CoroutineLocal;
 use crate::{impl_current_for, impl_display_by_debug, impl_for_named};
+use std::any::Any;
 use std::collections::VecDeque;
 use std::ffi::c_longlong;
 use std::fmt::{Debug, Formatter};
@@ -17,11 +20,37 @@ pub mod local;
 /// Coroutine listener abstraction and impl.
 pub mod listener;
 
+/// Compact coroutine ID.
+pub mod id;
+
+/// Generator built on coroutine.
+pub mod generator;
+
 #[cfg(feature = "korosensei")]
 pub use korosensei::Coroutine;
 #[cfg(feature = "korosensei")]
 mod korosensei;
 
+/// Stack pool for coroutines.
+#[cfg(feature = "korosensei")]
+pub(crate) mod stack_pool;
+
+/// Enable or disable filling the new coroutine stacks with canary pattern, then the peak
+/// stack usage can be measured when the coroutine finished. It's only supported on unix now.
+///
+/// Filling the stacks is expensive, so it's disabled by default.
+#[cfg(feature = "korosensei")]
+pub fn set_stack_canary(enable: bool) {
+    stack_pool::StackPool::set_canary(enable);
+}
+
+/// Returns `true` if filling the new coroutine stacks with canary pattern is enabled.
+#[cfg(feature = "korosensei")]
+#[must_use]
+pub fn is_stack_canary() -> bool {
+    stack_pool::StackPool::is_canary()
+}
+
 /// Create a new coroutine.
 #[macro_export]
 macro_rules! co {
@@ -66,6 +95,11 @@ impl<'c, Param, Yield, Return> Coroutine<'c, Param, Yield, Return> {
         &self.name
     }
 
+    /// Get the ID of this coroutine, prefer it to the name as the key of lookups.
+    pub fn id(&self) -> CoroutineId {
+        self.id
+    }
+
     /// Returns the current state of this `StateCoroutine`.
     pub fn state(&self) -> CoroutineState<Yield, Return>
     where
@@ -75,6 +109,35 @@ impl<'c, Param, Yield, Return> Coroutine<'c, Param, Yield, Return> {
         self.state.get()
     }
 
+    /// Returns the peak stack usage of this finished coroutine, including the stacks
+    /// allocated by [`Coroutine::maybe_grow`].
+    ///
+    /// Returns `None` if the stack canary is disabled, see [`set_stack_canary`].
+    pub fn stack_peak(&self) -> Option<usize> {
+        self.stack_peak.get()
+    }
+
+    /// Takes the error of this coroutine which completed with errors, the panic payload
+    /// is kept in it.
+    ///
+    /// Returns `None` if the coroutine didn't fail, or the error has been taken.
+    pub fn take_error(&self) -> Option<JoinError> {
+        self.error.take()
+    }
+
+    /// Set the type-erased result of this coroutine, it's taken by the scheduler
+    /// when the coroutine completes.
+    pub(crate) fn set_result(&self, result: Box<dyn Any + Send>) {
+        self.result.set(Some(result));
+    }
+
+    /// Takes the type-erased result of this completed coroutine.
+    ///
+    /// Returns `None` if no result is set, or the result has been taken.
+    pub(crate) fn take_result(&self) -> Option<Box<dyn Any + Send>> {
+        self.result.take()
+    }
+
     /// Add a listener to this coroutine.
     pub fn add_listener(&mut self, listener: impl Listener<Yield, Return> + 'c) {
         self.add_raw_listener(Box::leak(Box::new(listener)));
@@ -168,9 +231,14 @@ where
         if let CoroutineState::Error(e) = current {
             return Ok(CoroutineState::Error(e));
         }
+        if CoroutineState::Cancelled == current {
+            return Ok(CoroutineState::Cancelled);
+        }
         Self::init_current(self);
+        CoroutineLocal::init_current(&self.local);
         self.running()?;
         let r = self.raw_resume(arg);
+        CoroutineLocal::clean_current();
         Self::clean_current();
         r
     }
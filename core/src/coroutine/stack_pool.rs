@@ -0,0 +1,293 @@
+use crate::common::now;
+use crate::coroutine::StackInfo;
+use corosensei::stack::{DefaultStack, Stack, StackPointer};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(windows)]
+use corosensei::stack::StackTebFields;
+
+/// Whether to fill the new stacks with [`CANARY`].
+static STACK_CANARY: AtomicBool = AtomicBool::new(false);
+
+/// The pattern used to fill the new stacks.
+const CANARY: u8 = 0xA5;
+
+thread_local! {
+    static STACK_POOL: RefCell<StackPool> = RefCell::new(StackPool::default());
+}
+
+/// A stack allocated from the [`StackPool`], it will be recycled into the
+/// pool of the dropping thread.
+#[repr(C)]
+#[derive(educe::Educe)]
+#[educe(Debug)]
+pub(crate) struct PooledStack {
+    stack_size: usize,
+    canary: bool,
+    #[educe(Debug(ignore))]
+    stack: Option<DefaultStack>,
+}
+
+impl PooledStack {
+    /// Returns `true` if this stack is filled with canary pattern.
+    pub(crate) fn canary(&self) -> bool {
+        self.canary
+    }
+
+    fn inner(&self) -> &DefaultStack {
+        self.stack.as_ref().expect("stack already recycled")
+    }
+}
+
+unsafe impl Stack for PooledStack {
+    #[inline]
+    fn base(&self) -> StackPointer {
+        self.inner().base()
+    }
+
+    #[inline]
+    fn limit(&self) -> StackPointer {
+        self.inner().limit()
+    }
+
+    #[cfg(windows)]
+    #[inline]
+    fn teb_fields(&self) -> StackTebFields {
+        self.inner().teb_fields()
+    }
+
+    #[cfg(windows)]
+    #[inline]
+    fn update_teb_fields(&mut self, stack_limit: usize, guaranteed_stack_bytes: usize) {
+        self.stack
+            .as_mut()
+            .expect("stack already recycled")
+            .update_teb_fields(stack_limit, guaranteed_stack_bytes);
+    }
+}
+
+impl Drop for PooledStack {
+    fn drop(&mut self) {
+        if let Some(stack) = self.stack.take() {
+            StackPool::recycle(self.stack_size, stack);
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(educe::Educe)]
+#[educe(Debug)]
+struct IdleStack {
+    stack_size: usize,
+    #[educe(Debug(ignore))]
+    stack: DefaultStack,
+    recycle_time: u64,
+}
+
+/// The stack pool impls, every thread(usually event-loop) has its own pool.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub(crate) struct StackPool {
+    //空闲栈，越靠前回收得越早
+    idle: VecDeque<IdleStack>,
+    //最少保留的栈数
+    min_count: usize,
+    //空闲栈的最大存活时间，单位ns
+    keep_alive_time: u64,
+}
+
+impl StackPool {
+    /// Init the stack pool of current thread, and keep `min_count` stacks warm.
+    ///
+    /// # Errors
+    /// if stack allocate failed.
+    pub(crate) fn init(
+        stack_size: usize,
+        min_count: usize,
+        keep_alive_time: u64,
+    ) -> std::io::Result<()> {
+        let stack_size = Self::align(stack_size);
+        STACK_POOL.with(|pool| {
+            let mut pool = pool.borrow_mut();
+            pool.min_count = min_count;
+            pool.keep_alive_time = keep_alive_time;
+            let recycle_time = now();
+            while pool.idle.len() < min_count {
+                pool.idle.push_back(IdleStack {
+                    stack_size,
+                    stack: DefaultStack::new(stack_size)?,
+                    recycle_time,
+                });
+            }
+            Ok(())
+        })
+    }
+
+    /// Allocate a stack with at least `stack_size` bytes, reuse the idle stack if possible.
+    ///
+    /// # Errors
+    /// if stack allocate failed.
+    pub(crate) fn allocate(stack_size: usize) -> std::io::Result<PooledStack> {
+        let stack_size = Self::align(stack_size);
+        let idle = STACK_POOL
+            .try_with(|pool| {
+                let mut pool = pool.borrow_mut();
+                let index = pool
+                    .idle
+                    .iter()
+                    .rposition(|idle| idle.stack_size == stack_size)?;
+                pool.idle.remove(index)
+            })
+            .ok()
+            .flatten();
+        let stack = match idle {
+            Some(idle) => idle.stack,
+            None => DefaultStack::new(stack_size)?,
+        };
+        let canary = Self::fill_canary(&stack);
+        Ok(PooledStack {
+            stack_size,
+            canary,
+            stack: Some(stack),
+        })
+    }
+
+    /// Enable or disable filling the new stacks with canary pattern,
+    /// it's only supported on unix now.
+    pub(crate) fn set_canary(enable: bool) {
+        STACK_CANARY.store(enable, Ordering::Release);
+    }
+
+    /// Returns `true` if filling the new stacks with canary pattern is enabled.
+    pub(crate) fn is_canary() -> bool {
+        STACK_CANARY.load(Ordering::Acquire)
+    }
+
+    /// Returns the peak usage of the stack which filled with canary pattern,
+    /// the stack must be still alive.
+    pub(crate) fn peak_usage(info: &StackInfo) -> usize {
+        // skip the guard page
+        let bottom = info.stack_bottom + crate::common::page_size();
+        let len = info.stack_top.saturating_sub(bottom);
+        let stack = unsafe { std::slice::from_raw_parts(bottom as *const u8, len) };
+        stack
+            .iter()
+            .position(|b| *b != CANARY)
+            .map_or(0, |untouched| len - untouched)
+    }
+
+    #[allow(unused_variables)]
+    fn fill_canary(stack: &DefaultStack) -> bool {
+        if !Self::is_canary() {
+            return false;
+        }
+        cfg_if::cfg_if! {
+            if #[cfg(unix)] {
+                // skip the guard page
+                let start = stack.limit().get() + crate::common::page_size();
+                let len = stack.base().get().saturating_sub(start);
+                unsafe { std::ptr::write_bytes(start as *mut u8, CANARY, len) };
+                true
+            } else {
+                false
+            }
+        }
+    }
+
+    /// Release the idle stacks which exceed `keep_alive_time`,
+    /// but at least `min_count` stacks will be kept.
+    pub(crate) fn clean() {
+        _ = STACK_POOL.try_with(|pool| pool.borrow_mut().release_expired());
+    }
+
+    /// Returns the number of idle stacks in the pool of current thread.
+    #[cfg(test)]
+    pub(crate) fn idle_count() -> usize {
+        STACK_POOL.with(|pool| pool.borrow().idle.len())
+    }
+
+    fn recycle(stack_size: usize, stack: DefaultStack) {
+        Self::release_dirty_pages(&stack);
+        // the pool may have been destroyed when the thread exits
+        _ = STACK_POOL.try_with(|pool| {
+            let mut pool = pool.borrow_mut();
+            pool.idle.push_back(IdleStack {
+                stack_size,
+                stack,
+                recycle_time: now(),
+            });
+            pool.release_expired();
+        });
+    }
+
+    fn release_expired(&mut self) {
+        let now = now();
+        while self.idle.len() > self.min_count {
+            match self.idle.front() {
+                Some(idle) if idle.recycle_time.saturating_add(self.keep_alive_time) <= now => {
+                    drop(self.idle.pop_front());
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn align(stack_size: usize) -> usize {
+        stack_size.max(crate::common::page_size())
+    }
+
+    /// Return the dirty pages to the OS, they will be zero-filled on next access.
+    #[allow(unused_variables)]
+    fn release_dirty_pages(stack: &DefaultStack) {
+        #[cfg(unix)]
+        {
+            // skip the guard page
+            let start = stack.limit().get() + crate::common::page_size();
+            let len = stack.base().get().saturating_sub(start);
+            if len > 0 {
+                unsafe {
+                    _ = libc::madvise(start as *mut libc::c_void, len, libc::MADV_DONTNEED);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_peak_usage() -> std::io::Result<()> {
+        StackPool::set_canary(true);
+        let stack = StackPool::allocate(crate::common::page_size() * 4)?;
+        StackPool::set_canary(false);
+        assert!(stack.canary());
+        let info = StackInfo::from(&stack);
+        assert_eq!(0, StackPool::peak_usage(&info));
+        unsafe { std::ptr::write_bytes((info.stack_top - 100) as *mut u8, 0, 10) };
+        assert_eq!(100, StackPool::peak_usage(&info));
+        Ok(())
+    }
+
+    #[test]
+    fn test_recycle() -> std::io::Result<()> {
+        StackPool::init(crate::common::page_size() * 4, 1, u64::MAX)?;
+        assert_eq!(1, StackPool::idle_count());
+        let stack = StackPool::allocate(crate::common::page_size() * 4)?;
+        assert_eq!(0, StackPool::idle_count());
+        let base = stack.base();
+        drop(stack);
+        assert_eq!(1, StackPool::idle_count());
+        let stack = StackPool::allocate(crate::common::page_size() * 4)?;
+        assert_eq!(base, stack.base());
+        drop(stack);
+        StackPool::init(crate::common::page_size() * 4, 0, 0)?;
+        StackPool::clean();
+        assert_eq!(0, StackPool::idle_count());
+        Ok(())
+    }
+}
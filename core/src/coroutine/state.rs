@@ -1,4 +1,5 @@
 use crate::common::constants::{CoroutineState, SyscallName, SyscallState};
+use crate::common::join_error::JoinError;
 use crate::common::now;
 use crate::coroutine::listener::Listener;
 use crate::coroutine::Coroutine;
@@ -55,6 +56,32 @@ where
         ))
     }
 
+    /// suspend -> ready, even if the suspend time has not yet arrived.
+    ///
+    /// # Errors
+    /// if change state fails.
+    pub(crate) fn wakeup(&self) -> std::io::Result<()> {
+        let current = self.state();
+        match current {
+            CoroutineState::Ready => return Ok(()),
+            CoroutineState::Suspend(_, _) => {
+                let new_state = CoroutineState::Ready;
+                let old_state = self.change_state(new_state);
+                self.on_ready(self, old_state);
+                return Ok(());
+            }
+            _ => {}
+        }
+        Err(Error::new(
+            ErrorKind::Other,
+            format!(
+                "{} unexpected {current}->{:?}",
+                self.name(),
+                CoroutineState::<Yield, Return>::Ready
+            ),
+        ))
+    }
+
     /// ready -> running
     /// syscall -> running
     ///
@@ -168,6 +195,7 @@ where
             let new_state = CoroutineState::Complete(val);
             let old_state = self.change_state(new_state);
             self.on_complete(self, old_state, val);
+            self.local.clear();
             return Ok(());
         }
         Err(Error::new(
@@ -184,12 +212,34 @@ where
     ///
     /// # Errors
     /// if change state fails.
-    pub(super) fn error(&self, msg: &'static str) -> std::io::Result<()> {
+    pub(super) fn error(&self, error: JoinError) -> std::io::Result<()> {
         let current = self.state();
+        let new_state = CoroutineState::Error(error.reason());
         if CoroutineState::Running == current {
-            let new_state = CoroutineState::Error(msg);
+            let message = String::from(error.message());
+            //先保存错误，监听器可以在状态变更时取走
+            self.error.set(Some(error));
             let old_state = self.change_state(new_state);
-            self.on_error(self, old_state, msg);
+            self.on_error(self, old_state, &message);
+            self.local.clear();
+            return Ok(());
+        }
+        Err(Error::new(
+            ErrorKind::Other,
+            format!("{} unexpected {current}->{new_state:?}", self.name()),
+        ))
+    }
+
+    /// running -> cancelled
+    ///
+    /// # Errors
+    /// if change state fails.
+    pub(super) fn cancel(&self) -> std::io::Result<()> {
+        let current = self.state();
+        if CoroutineState::Running == current {
+            let new_state = CoroutineState::Cancelled;
+            _ = self.change_state(new_state);
+            self.local.clear();
             return Ok(());
         }
         Err(Error::new(
@@ -197,7 +247,7 @@ where
             format!(
                 "{} unexpected {current}->{:?}",
                 self.name(),
-                CoroutineState::<Yield, Return>::Error(msg)
+                CoroutineState::<Yield, Return>::Cancelled
             ),
         ))
     }
@@ -277,9 +327,35 @@ mod tests {
         let co = co!(|_: &Suspender<(), ()>, ()| {})?;
         assert_eq!(CoroutineState::Ready, co.state());
         co.running()?;
-        co.error("test error, ignore it")?;
+        co.error(JoinError::panic(Box::new("test error, ignore it")))?;
         assert_eq!(CoroutineState::Error("test error, ignore it"), co.state());
-        assert!(co.error("abc").is_err());
+        assert!(co.error(JoinError::panic(Box::new("abc"))).is_err());
+        let error = co.take_error().expect("error not found");
+        assert_eq!("test error, ignore it", error.message());
+        assert!(co.take_error().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel() -> std::io::Result<()> {
+        let co = co!(|_: &Suspender<(), ()>, ()| {})?;
+        assert_eq!(CoroutineState::Ready, co.state());
+        assert!(co.cancel().is_err());
+        co.running()?;
+        co.cancel()?;
+        assert_eq!(CoroutineState::Cancelled, co.state());
+        assert!(co.running().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_wakeup() -> std::io::Result<()> {
+        let co = co!(|_: &Suspender<(), ()>, ()| {})?;
+        co.running()?;
+        co.suspend((), u64::MAX)?;
+        assert!(co.ready().is_err());
+        co.wakeup()?;
+        assert_eq!(CoroutineState::Ready, co.state());
         Ok(())
     }
 }
@@ -6,6 +6,16 @@ thread_local! {
     #[allow(clippy::missing_const_for_thread_local)]
     static TIMESTAMP: crossbeam_utils::atomic::AtomicCell<std::collections::VecDeque<u64>> =
         const { crossbeam_utils::atomic::AtomicCell::new(std::collections::VecDeque::new()) };
+    static CANCEL_REQUESTED: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    static CANCELLED: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Unwind the current coroutine if it has been asked to cancel.
+fn unwind_if_cancel_requested() {
+    if CANCEL_REQUESTED.with(|c| c.replace(false)) {
+        CANCELLED.with(|c| c.set(true));
+        std::panic::resume_unwind(Box::new("coroutine cancelled"));
+    }
 }
 
 impl<Param, Yield> Suspender<'_, Param, Yield> {
@@ -45,6 +55,16 @@ impl<Param, Yield> Suspender<'_, Param, Yield> {
             })
             .unwrap_or(0)
     }
+
+    /// Ask the coroutine which will be resumed to unwind at its suspension point.
+    pub(crate) fn request_cancel(cancel: bool) {
+        CANCEL_REQUESTED.with(|c| c.set(cancel));
+    }
+
+    /// Returns `true` if the coroutine has been unwound by cancellation.
+    pub(crate) fn take_cancelled() -> bool {
+        CANCELLED.with(|c| c.replace(false))
+    }
 }
 
 #[allow(clippy::must_use_candidate)]
@@ -87,8 +107,20 @@ mod korosensei {
         }
 
         /// Suspend the execution of current coroutine with an arg.
+        ///
+        /// If the coroutine is cancelled while suspended, it will unwind from here.
         pub fn suspend_with(&self, arg: Yield) -> Param {
+            crate::dump::record_suspend_backtrace();
+            let param = self.uncancellable_suspend_with(arg);
+            super::unwind_if_cancel_requested();
+            param
+        }
+
+        /// Suspend the execution of current coroutine with an arg, and never unwind
+        /// from here, it's used where unwinding is not allowed, such as signal handler.
+        pub(crate) fn uncancellable_suspend_with(&self, arg: Yield) -> Param {
             Self::clean_current();
+            crate::dump::record_suspend_sp();
             let param = self.inner.suspend(arg);
             Self::init_current(self);
             param
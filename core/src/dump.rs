@@ -0,0 +1,271 @@
+use crate::common::constants::{CoroutineState, SyscallName, SyscallState};
+use crate::common::now;
+use crate::common::ordered_work_steal::Ordered;
+use crate::scheduler::{SchedulableCoroutine, SchedulableCoroutineState};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::ffi::c_longlong;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The latest snapshots of all scheduled coroutines, the key is the coroutine name.
+static SNAPSHOTS: Lazy<DashMap<String, CoroutineDump>> = Lazy::new(DashMap::new);
+
+/// Whether to record the snapshots of coroutines.
+static DUMP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether to capture backtrace when coroutines suspend.
+static CAPTURE_BACKTRACE: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static SUSPEND_SP: Cell<Option<usize>> = const { Cell::new(None) };
+    static SUSPEND_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Enable or disable recording the snapshots of coroutines, the recorded snapshots are
+/// dropped when disabled.
+///
+/// The snapshots are updated every time a coroutine is scheduled, so it's disabled by default.
+pub fn set_dump_enabled(enable: bool) {
+    DUMP_ENABLED.store(enable, Ordering::Release);
+    if !enable {
+        SNAPSHOTS.clear();
+    }
+}
+
+/// Returns `true` if recording the snapshots of coroutines is enabled.
+#[must_use]
+pub fn is_dump_enabled() -> bool {
+    DUMP_ENABLED.load(Ordering::Acquire)
+}
+
+/// Enable or disable capturing backtrace at the suspension point of coroutines,
+/// it takes effect only if dump is enabled, see [`set_dump_enabled`].
+///
+/// Capturing backtrace is expensive, so it's disabled by default.
+pub fn set_capture_backtrace(enable: bool) {
+    CAPTURE_BACKTRACE.store(enable, Ordering::Release);
+}
+
+/// Returns `true` if capturing backtrace at the suspension point is enabled.
+#[must_use]
+pub fn is_capture_backtrace() -> bool {
+    CAPTURE_BACKTRACE.load(Ordering::Acquire)
+}
+
+/// Record the stack pointer of current coroutine if enabled, it's async-signal-safe.
+pub(crate) fn record_suspend_sp() {
+    if !is_dump_enabled() {
+        return;
+    }
+    SUSPEND_SP.with(|sp| sp.set(Some(psm::stack_pointer() as usize)));
+}
+
+/// Capture the backtrace of current coroutine if enabled.
+pub(crate) fn record_suspend_backtrace() {
+    if is_dump_enabled() && is_capture_backtrace() {
+        SUSPEND_BACKTRACE.with(|bt| *bt.borrow_mut() = Some(Backtrace::force_capture()));
+    }
+}
+
+/// The snapshot of a coroutine.
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct CoroutineDump {
+    /// The name of the coroutine.
+    pub name: String,
+    /// The name of the scheduler which scheduled the coroutine recently.
+    pub scheduler: String,
+    /// The state of the coroutine.
+    pub state: SchedulableCoroutineState,
+    /// The syscall which the coroutine is executing.
+    pub syscall: Option<SyscallName>,
+    /// The timestamp in ns when the suspended coroutine will be woken up.
+    pub deadline: Option<u64>,
+    /// The priority of the coroutine.
+    pub priority: Option<c_longlong>,
+    /// The total size of the stacks allocated by the coroutine.
+    pub stack_size: usize,
+    /// The used stack size at the latest suspension point.
+    pub stack_used: Option<usize>,
+    /// The backtrace captured at the latest suspension point,
+    /// see [`set_capture_backtrace`].
+    pub backtrace: Option<Arc<Backtrace>>,
+}
+
+impl CoroutineDump {
+    /// Update the snapshot of the coroutine, it should be called by the scheduler.
+    pub(crate) fn record(scheduler: &str, co: &SchedulableCoroutine) {
+        Self::update(scheduler, co, co.state(), None, None);
+    }
+
+    /// Update the snapshot of the coroutine which will be resumed, it should be called by the scheduler.
+    pub(crate) fn resuming(scheduler: &str, co: &SchedulableCoroutine) {
+        // 清理未被调度器消费的挂起点
+        _ = SUSPEND_SP.with(Cell::take);
+        _ = SUSPEND_BACKTRACE.with(|bt| bt.borrow_mut().take());
+        let state = match co.state() {
+            CoroutineState::Ready | CoroutineState::Suspend((), _) => CoroutineState::Running,
+            state => state,
+        };
+        Self::update(scheduler, co, state, None, None);
+    }
+
+    /// Update the snapshot of the coroutine which just returned from `resume`,
+    /// it should be called by the scheduler.
+    pub(crate) fn resumed(scheduler: &str, co: &SchedulableCoroutine) {
+        let sp = SUSPEND_SP.with(Cell::take);
+        let backtrace = SUSPEND_BACKTRACE.with(|bt| bt.borrow_mut().take());
+        Self::update(scheduler, co, co.state(), sp, backtrace);
+    }
+
+    fn update(
+        scheduler: &str,
+        co: &SchedulableCoroutine,
+        state: SchedulableCoroutineState,
+        sp: Option<usize>,
+        backtrace: Option<Backtrace>,
+    ) {
+        if !is_dump_enabled() {
+            return;
+        }
+        let (syscall, deadline) = match state {
+            CoroutineState::Suspend((), timestamp) => (None, Some(timestamp)),
+            CoroutineState::Syscall((), syscall, SyscallState::Suspend(timestamp)) => {
+                (Some(syscall), Some(timestamp))
+            }
+            CoroutineState::Syscall((), syscall, _) => (Some(syscall), None),
+            _ => (None, None),
+        };
+        let stack_infos = co.stack_infos_ref();
+        let stack_size = stack_infos
+            .iter()
+            .map(|info| info.stack_top - info.stack_bottom)
+            .sum();
+        let stack_used = sp.map(|sp| {
+            let mut used = 0;
+            for info in stack_infos {
+                if info.stack_bottom <= sp && sp < info.stack_top {
+                    return used + info.stack_top - sp;
+                }
+                used += info.stack_top - info.stack_bottom;
+            }
+            used
+        });
+        let backtrace = backtrace.map(Arc::new);
+        if let Some(mut dump) = SNAPSHOTS.get_mut(co.name()) {
+            if dump.scheduler != scheduler {
+                dump.scheduler = String::from(scheduler);
+            }
+            dump.state = state;
+            dump.syscall = syscall;
+            dump.deadline = deadline;
+            dump.stack_size = stack_size;
+            if stack_used.is_some() {
+                dump.stack_used = stack_used;
+                dump.backtrace = backtrace;
+            }
+            return;
+        }
+        _ = SNAPSHOTS.insert(
+            String::from(co.name()),
+            CoroutineDump {
+                name: String::from(co.name()),
+                scheduler: String::from(scheduler),
+                state,
+                syscall,
+                deadline,
+                priority: co.priority(),
+                stack_size,
+                stack_used,
+                backtrace,
+            },
+        );
+    }
+
+    /// Forget the snapshot of the finished or dropped coroutine.
+    pub(crate) fn remove(co_name: &str) {
+        if is_dump_enabled() {
+            _ = SNAPSHOTS.remove(co_name);
+        }
+    }
+}
+
+impl Display for CoroutineDump {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{}\" {:?}", self.name, self.state)?;
+        if let Some(deadline) = self.deadline {
+            let left = deadline.saturating_sub(now());
+            write!(f, " wakeup in {}ms", left / 1_000_000)?;
+        }
+        if let Some(priority) = self.priority {
+            write!(f, " priority:{priority}")?;
+        }
+        match self.stack_used {
+            Some(used) => writeln!(f, " stack:{used}/{}", self.stack_size)?,
+            None => writeln!(f, " stack:?/{}", self.stack_size)?,
+        }
+        if let Some(backtrace) = &self.backtrace {
+            for line in backtrace.to_string().lines() {
+                writeln!(f, "    {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The snapshot of a scheduler.
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct SchedulerDump {
+    /// The name of the scheduler.
+    pub name: String,
+    /// The snapshots of the coroutines which scheduled by the scheduler.
+    pub coroutines: Vec<CoroutineDump>,
+}
+
+impl SchedulerDump {
+    /// Collect the snapshots of the coroutines which scheduled by the scheduler.
+    pub(crate) fn collect(scheduler: &str) -> Self {
+        let mut coroutines: Vec<CoroutineDump> = SNAPSHOTS
+            .iter()
+            .filter(|dump| dump.scheduler == scheduler)
+            .map(|dump| dump.value().clone())
+            .collect();
+        coroutines.sort_by(|a, b| a.name.cmp(&b.name));
+        Self {
+            name: String::from(scheduler),
+            coroutines,
+        }
+    }
+}
+
+impl Display for SchedulerDump {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} ({} coroutines):", self.name, self.coroutines.len())?;
+        for co in &self.coroutines {
+            write!(f, "  {co}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The snapshot of the runtime.
+#[repr(C)]
+#[derive(Debug, Clone, Default)]
+pub struct Dump {
+    /// The snapshots of the schedulers.
+    pub schedulers: Vec<SchedulerDump>,
+}
+
+impl Display for Dump {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for scheduler in &self.schedulers {
+            write!(f, "{scheduler}")?;
+        }
+        Ok(())
+    }
+}
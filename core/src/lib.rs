@@ -73,6 +73,9 @@ pub mod scheduler;
 /// Coroutine pool abstraction and impl.
 pub mod co_pool;
 
+/// Dump the coroutines for diagnosis.
+pub mod dump;
+
 /// net abstraction and impl.
 #[allow(dead_code)]
 #[cfg(feature = "net")]
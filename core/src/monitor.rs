@@ -73,7 +73,8 @@ impl Monitor {
                 set.thread_set_mask()
                     .expect("Failed to remove SIGURG signal mask!");
                 if let Some(suspender) = SchedulableSuspender::current() {
-                    suspender.suspend();
+                    //不能在信号处理函数中展开栈，所以这里不响应取消
+                    suspender.uncancellable_suspend_with(());
                 }
             }
         }
@@ -96,12 +97,8 @@ impl Monitor {
                         .name("open-coroutine-monitor".to_string())
                         .spawn(|| {
                             info!("monitor started !");
-                            if catch!(
-                                Self::monitor_thread_main,
-                                String::from("Monitor thread run failed without message"),
-                                String::from("Monitor thread")
-                            )
-                            .is_ok()
+                            if catch!(Self::monitor_thread_main, String::from("Monitor thread"))
+                                .is_ok()
                             {
                                 info!("monitor stopped !");
                             }
@@ -200,7 +197,8 @@ impl<Yield, Return> Listener<Yield, Return> for MonitorListener {
             CoroutineState::Suspend(_, _)
             | CoroutineState::Syscall(_, _, _)
             | CoroutineState::Complete(_)
-            | CoroutineState::Error(_) => {
+            | CoroutineState::Error(_)
+            | CoroutineState::Cancelled => {
                 if let Some(node) = local.get(NOTIFY_NODE) {
                     _ = Monitor::remove(node);
                 }
@@ -1,32 +1,34 @@
+use crate::co_pool::task::Task;
 use crate::co_pool::CoroutinePool;
 use crate::common::beans::BeanFactory;
 use crate::common::constants::{CoroutineState, PoolState, SyscallName, SyscallState, SLICE};
+use crate::config::Config;
+use crate::coroutine::id::CoroutineId;
+use crate::coroutine::stack_pool::StackPool;
 use crate::net::selector::{Event, Events, Poller, Selector};
-use crate::scheduler::SchedulableCoroutine;
+use crate::scheduler::{SchedulableCoroutine, Scheduler};
 use crate::{error, impl_current_for, impl_display_by_debug, info};
-use dashmap::DashSet;
+use dashmap::DashMap;
 use once_cell::sync::Lazy;
 use rand::Rng;
-use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::ffi::c_int;
 use std::io::{Error, ErrorKind};
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread::JoinHandle;
 use std::time::Duration;
 
 cfg_if::cfg_if! {
     if #[cfg(all(target_os = "linux", feature = "io_uring"))] {
-        use dashmap::DashMap;
         use libc::{epoll_event, iovec, mode_t, msghdr, off_t, size_t, sockaddr, socklen_t};
-        use std::ffi::{c_longlong, c_uint};
+        use std::ffi::{c_char, c_longlong, c_uint, c_void};
     }
 }
 
 cfg_if::cfg_if! {
     if #[cfg(all(windows, feature = "iocp"))] {
-        use dashmap::DashMap;
         use std::ffi::{c_longlong, c_uint};
         use windows_sys::core::{PCSTR, PSTR};
         use windows_sys::Win32::Networking::WinSock::{
@@ -36,10 +38,21 @@ cfg_if::cfg_if! {
     }
 }
 
+/// A request to drain the local queue of the `EventLoop` on its own thread.
+#[derive(Debug, Default)]
+struct ShutdownRequest<'e> {
+    //同一时刻只允许一个请求
+    serial: Mutex<()>,
+    requested: AtomicBool,
+    tasks: Mutex<Option<std::io::Result<Vec<Task<'e>>>>>,
+    handled: Condvar,
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub(crate) struct EventLoop<'e> {
     stop: Arc<(Mutex<bool>, Condvar)>,
+    shutdown_request: ShutdownRequest<'e>,
     shared_stop: Arc<(Mutex<AtomicUsize>, Condvar)>,
     cpu: usize,
     #[cfg(any(
@@ -55,6 +68,8 @@ pub(crate) struct EventLoop<'e> {
     syscall_wait_table: DashMap<usize, Arc<(Mutex<Option<c_longlong>>, Condvar)>>,
     selector: Poller,
     pool: CoroutinePool<'e>,
+    min_memory_count: usize,
+    memory_keep_alive_time: u64,
     phantom_data: PhantomData<&'e EventLoop<'e>>,
 }
 
@@ -89,7 +104,11 @@ impl Default for EventLoop<'_> {
     }
 }
 
-static COROUTINE_TOKENS: Lazy<DashSet<usize>> = Lazy::new(DashSet::new);
+static COROUTINE_TOKENS: Lazy<DashMap<usize, CoroutineId>> = Lazy::new(DashMap::new);
+
+/// The tokens of io operations are unique, they never conflict with the slab indexes of
+/// the coroutine IDs.
+static OPERATION_TOKEN: AtomicUsize = AtomicUsize::new(1 << (usize::BITS - 1));
 
 impl<'e> EventLoop<'e> {
     pub(super) fn new(
@@ -101,8 +120,47 @@ impl<'e> EventLoop<'e> {
         keep_alive_time: u64,
         shared_stop: Arc<(Mutex<AtomicUsize>, Condvar)>,
     ) -> std::io::Result<Self> {
+        Self::with_config(
+            name,
+            cpu,
+            &Config::new(
+                1,
+                stack_size,
+                min_size,
+                max_size,
+                keep_alive_time,
+                0,
+                0,
+                true,
+            ),
+            shared_stop,
+        )
+    }
+
+    /// Create a new `EventLoop` with the pool settings in `config`.
+    pub(super) fn with_config(
+        name: String,
+        cpu: usize,
+        config: &Config,
+        shared_stop: Arc<(Mutex<AtomicUsize>, Condvar)>,
+    ) -> std::io::Result<Self> {
+        let mut pool = CoroutinePool::with_ready_queue(
+            name,
+            config.stack_size(),
+            config.min_size(),
+            config.max_size(),
+            config.keep_alive_time(),
+            config.ready_queue(),
+        );
+        pool.set_task_capacity(config.task_capacity());
+        pool.set_reject_policy(config.reject_policy());
+        pool.set_result_ttl(config.result_ttl());
+        for listener in super::listeners() {
+            pool.add_raw_listener(listener);
+        }
         Ok(EventLoop {
             stop: Arc::new((Mutex::new(false), Condvar::new())),
+            shutdown_request: ShutdownRequest::default(),
             shared_stop,
             cpu,
             #[cfg(any(
@@ -116,7 +174,9 @@ impl<'e> EventLoop<'e> {
             ))]
             syscall_wait_table: DashMap::new(),
             selector: Poller::new()?,
-            pool: CoroutinePool::new(name, stack_size, min_size, max_size, keep_alive_time),
+            pool,
+            min_memory_count: config.min_memory_count(),
+            memory_keep_alive_time: config.memory_keep_alive_time(),
             phantom_data: PhantomData,
         })
     }
@@ -124,12 +184,16 @@ impl<'e> EventLoop<'e> {
     #[allow(trivial_numeric_casts, clippy::cast_possible_truncation)]
     fn token(syscall: SyscallName) -> usize {
         if let Some(co) = SchedulableCoroutine::current() {
-            let boxed: &'static mut CString = Box::leak(Box::from(
-                CString::new(co.name()).expect("build name failed!"),
-            ));
-            let cstr: &'static CStr = boxed.as_c_str();
-            let token = cstr.as_ptr().cast::<c_void>() as usize;
-            assert!(COROUTINE_TOKENS.insert(token));
+            let co_id = co.id();
+            let token = if SyscallName::nio() == syscall {
+                //同一协程的多个事件共用token，使用slab索引，在32位平台上也不会溢出；
+                //索引复用后旧事件最多造成一次虚假唤醒，被hook的系统调用会重试
+                co_id.index() as usize
+            } else {
+                //超时的io操作仍可能在之后完成，因此token不能复用
+                OPERATION_TOKEN.fetch_add(1, Ordering::Relaxed)
+            };
+            _ = COROUTINE_TOKENS.insert(token, co_id);
             return token;
         }
         unsafe {
@@ -140,7 +204,7 @@ impl<'e> EventLoop<'e> {
                     let thread_id = libc::pthread_self();
                 }
             }
-            let syscall_mask = <SyscallName as Into<&str>>::into(syscall).as_ptr() as usize;
+            let syscall_mask = syscall as usize;
             let token = thread_id as usize ^ syscall_mask;
             if SyscallName::nio() != syscall {
                 eprintln!("generate token:{token} for {syscall}");
@@ -240,6 +304,10 @@ impl<'e> EventLoop<'e> {
                         );
                     }
                 }
+                //协程已被取消，中断系统调用
+                if Scheduler::is_cancelling(co.name()) {
+                    return Err(Error::new(ErrorKind::Interrupted, "coroutine cancelled"));
+                }
             }
         }
 
@@ -316,11 +384,8 @@ impl<'e> EventLoop<'e> {
     }
 
     unsafe fn resume(&self, token: usize) {
-        if COROUTINE_TOKENS.remove(&token).is_none() {
-            return;
-        }
-        if let Ok(co_name) = CStr::from_ptr((token as *const c_void).cast::<c_char>()).to_str() {
-            self.try_resume(co_name);
+        if let Some((_, co_id)) = COROUTINE_TOKENS.remove(&token) {
+            self.try_resume(co_id);
         }
     }
 
@@ -359,17 +424,28 @@ impl<'e> EventLoop<'e> {
                         consumer.name(),
                         core_affinity::set_for_current(core_affinity::CoreId { id: consumer.cpu })
                     );
+                    if let Err(e) = StackPool::init(
+                        consumer.stack_size(),
+                        consumer.min_memory_count,
+                        consumer.memory_keep_alive_time,
+                    ) {
+                        error!("{} init stack pool failed: {e}", consumer.name());
+                    }
                     Self::init_current(consumer);
                     while PoolState::Running == consumer.state()
                         || !consumer.is_empty()
                         || consumer.get_running_size() > 0
                     {
                         _ = consumer.wait_event(Some(SLICE));
+                        consumer.handle_shutdown_request();
+                        StackPool::clean();
                     }
                     // notify stop flags
                     {
                         let (lock, cvar) = &*consumer.stop.clone();
                         let mut pending = lock.lock().expect("lock failed");
+                        //退出前处理已发出的请求，之后的请求由调用方直接处理
+                        consumer.handle_shutdown_request();
                         *pending = false;
                         cvar.notify_one();
                     }
@@ -391,6 +467,42 @@ impl<'e> EventLoop<'e> {
         }
     }
 
+    /// Stop this `EventLoop` forcefully, see [`CoroutinePool::shutdown_now`].
+    ///
+    /// The local queue is drained on the thread of this `EventLoop` if it has started.
+    pub(super) fn shutdown_now(&self) -> std::io::Result<Vec<Task<'e>>> {
+        if Self::current().is_some_and(|current| std::ptr::eq(current, self)) {
+            return self.pool.shutdown_now();
+        }
+        let request = &self.shutdown_request;
+        let _serial = request.serial.lock().expect("lock failed");
+        {
+            let (lock, _) = &*self.stop;
+            let running = lock.lock().expect("lock failed");
+            if !*running {
+                drop(running);
+                return self.pool.shutdown_now();
+            }
+            request.requested.store(true, Ordering::Release);
+        }
+        let mut tasks = request
+            .handled
+            .wait_while(request.tasks.lock().expect("lock failed"), |tasks| {
+                tasks.is_none()
+            })
+            .expect("lock failed");
+        tasks.take().expect("shutdown request not handled")
+    }
+
+    fn handle_shutdown_request(&self) {
+        let request = &self.shutdown_request;
+        if request.requested.swap(false, Ordering::AcqRel) {
+            let mut tasks = request.tasks.lock().expect("lock failed");
+            *tasks = Some(self.pool.shutdown_now());
+            request.handled.notify_one();
+        }
+    }
+
     fn get_thread_name(&self) -> String {
         format!("{}-thread", self.name())
     }
@@ -419,9 +531,12 @@ impl<'e> EventLoop<'e> {
 
     pub(super) fn stop(&self, wait_time: Duration) -> std::io::Result<()> {
         match self.state() {
-            PoolState::Running => {
-                if BeanFactory::remove_bean::<JoinHandle<()>>(&self.get_thread_name()).is_some() {
-                    assert_eq!(PoolState::Running, self.stopping()?);
+            PoolState::Running | PoolState::Stopping => {
+                let started =
+                    BeanFactory::remove_bean::<JoinHandle<()>>(&self.get_thread_name()).is_some();
+                //可能已被shutdown_now停止
+                if started || PoolState::Stopping == self.state() {
+                    _ = self.stopping()?;
                     //开启了单独的线程
                     let (lock, cvar) = &*self.stop;
                     let result = cvar
@@ -438,7 +553,6 @@ impl<'e> EventLoop<'e> {
                 }
                 Ok(())
             }
-            PoolState::Stopping => Err(Error::new(ErrorKind::Other, "should never happens")),
             PoolState::Stopped => Ok(()),
         }
     }
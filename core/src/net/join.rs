@@ -1,4 +1,6 @@
+use crate::common::join_error::{JoinError, JoinErrorKind};
 use crate::net::event_loop::EventLoop;
+use std::any::Any;
 use std::ffi::{c_char, CStr, CString};
 use std::io::{Error, ErrorKind};
 use std::sync::Arc;
@@ -7,21 +9,21 @@ use std::time::Duration;
 #[allow(missing_docs)]
 #[repr(C)]
 #[derive(Debug)]
-pub struct JoinHandle(&'static Arc<EventLoop<'static>>, *const c_char);
+pub struct JoinHandle(Option<&'static Arc<EventLoop<'static>>>, *mut c_char);
 
 impl JoinHandle {
     /// create `JoinHandle` instance.
-    pub(crate) fn err(pool: &'static Arc<EventLoop<'static>>) -> Self {
-        Self::new(pool, "")
+    pub(crate) fn new(pool: &'static Arc<EventLoop<'static>>, name: &str) -> Self {
+        let cstring = CString::new(name).expect("init JoinHandle failed!");
+        JoinHandle(Some(pool), cstring.into_raw())
     }
 
-    /// create `JoinHandle` instance.
-    pub(crate) fn new(pool: &'static Arc<EventLoop<'static>>, name: &str) -> Self {
-        let boxed: &'static mut CString = Box::leak(Box::from(
-            CString::new(name).expect("init JoinHandle failed!"),
-        ));
-        let cstr: &'static CStr = boxed.as_c_str();
-        JoinHandle(pool, cstr.as_ptr())
+    /// create `JoinHandle` instance for the task which was rejected when submitting,
+    /// joining it always gets a rejected [`JoinError`].
+    #[must_use]
+    pub fn rejected() -> Self {
+        let cstring = CString::new("").expect("init JoinHandle failed!");
+        JoinHandle(None, cstring.into_raw())
     }
 
     /// get the task name.
@@ -34,19 +36,100 @@ impl JoinHandle {
             .map_err(|_| Error::new(ErrorKind::InvalidInput, "Invalid task name"))
     }
 
+    /// cancel the task.
+    ///
+    /// A queued task will be dropped without running, and a running task will unwind at its
+    /// next suspension point, then the joiners will get a cancelled [`JoinError`].
+    ///
+    /// # Errors
+    /// if the task name is invalid.
+    pub fn cancel(&self) -> std::io::Result<()> {
+        let Some(pool) = self.0 else {
+            return Ok(());
+        };
+        let name = self.get_name()?;
+        if name.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Invalid task name"));
+        }
+        pool.try_cancel_task(name);
+        Ok(())
+    }
+
+    /// detach the task, its result will be dropped when it completes, see
+    /// [`CoroutinePool::detach_task`](crate::co_pool::CoroutinePool::detach_task).
+    ///
+    /// # Errors
+    /// if the task name is invalid, or the task is unknown or has been joined.
+    pub fn detach(&self) -> std::io::Result<()> {
+        let Some(pool) = self.0 else {
+            return Ok(());
+        };
+        let name = self.get_name()?;
+        if name.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Invalid task name"));
+        }
+        pool.detach_task(name)
+    }
+
+    /// Attach a callback which is called with the result when the task completes, it runs in
+    /// the event-loop which finished the task, see
+    /// [`CoroutinePool::on_complete`](crate::co_pool::CoroutinePool::on_complete).
+    ///
+    /// # Errors
+    /// if the task name is invalid, or the task is unknown or has been joined.
+    pub fn on_complete(
+        &self,
+        callback: impl FnOnce(Result<Box<dyn Any + Send>, JoinError>) + 'static,
+    ) -> std::io::Result<()> {
+        let Some(pool) = self.0 else {
+            callback(Err(JoinError::new(JoinErrorKind::Rejected)));
+            return Ok(());
+        };
+        let name = self.get_name()?;
+        if name.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Invalid task name"));
+        }
+        pool.on_complete(name, callback)
+    }
+
+    /// Submit a new task which runs with the result of this task after it completes,
+    /// see [`CoroutinePool::then`](crate::co_pool::CoroutinePool::then).
+    ///
+    /// # Errors
+    /// if the task name is invalid, or the task is unknown or has been joined.
+    pub fn then<R: Any + Send>(
+        &self,
+        func: impl FnOnce(Result<Box<dyn Any + Send>, JoinError>) -> R + 'static,
+    ) -> std::io::Result<Self> {
+        let Some(pool) = self.0 else {
+            return Ok(Self::rejected());
+        };
+        let name = self.get_name()?;
+        if name.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Invalid task name"));
+        }
+        Ok(Self::new(pool, &pool.then(name, func, None)?))
+    }
+
     /// join with `Duration`.
     ///
     /// # Errors
     /// see `timeout_at_join`.
-    pub fn timeout_join(&self, dur: Duration) -> std::io::Result<Result<Option<usize>, &str>> {
+    pub fn timeout_join(
+        &self,
+        dur: Duration,
+    ) -> std::io::Result<Result<Box<dyn Any + Send>, JoinError>> {
         self.timeout_at_join(crate::common::get_timeout_time(dur))
     }
 
-    /// join.
+    /// join, use [`Box::downcast`] to get the typed result.
+    ///
+    /// If the task failed, the [`JoinError`] tells why, and the panic payload can be
+    /// passed to [`std::panic::resume_unwind`].
     ///
     /// # Errors
     /// see `timeout_at_join`.
-    pub fn join(&self) -> std::io::Result<Result<Option<usize>, &str>> {
+    pub fn join(&self) -> std::io::Result<Result<Box<dyn Any + Send>, JoinError>> {
         self.timeout_at_join(u64::MAX)
     }
 
@@ -57,14 +140,23 @@ impl JoinHandle {
     pub fn timeout_at_join(
         &self,
         timeout_time: u64,
-    ) -> std::io::Result<Result<Option<usize>, &str>> {
+    ) -> std::io::Result<Result<Box<dyn Any + Send>, JoinError>> {
+        let Some(pool) = self.0 else {
+            return Ok(Err(JoinError::new(JoinErrorKind::Rejected)));
+        };
         let name = self.get_name()?;
         if name.is_empty() {
             return Err(Error::new(ErrorKind::InvalidInput, "Invalid task name"));
         }
-        self.0.wait_task_result(
+        pool.wait_task_result(
             name,
             Duration::from_nanos(timeout_time.saturating_sub(crate::common::now())),
         )
     }
 }
+
+impl Drop for JoinHandle {
+    fn drop(&mut self) {
+        drop(unsafe { CString::from_raw(self.1) });
+    }
+}
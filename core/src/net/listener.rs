@@ -0,0 +1,335 @@
+use crate::common::constants::{CoroutineState, SyscallName, SyscallState};
+use crate::coroutine::listener::Listener;
+use crate::coroutine::local::CoroutineLocal;
+
+/// The C compatible [`CoroutineState`] of the scheduled coroutines.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub enum RawCoroutineState {
+    /// see [`CoroutineState::Ready`].
+    Ready,
+    /// see [`CoroutineState::Running`].
+    Running,
+    /// see [`CoroutineState::Suspend`].
+    Suspend(u64),
+    /// see [`CoroutineState::Syscall`].
+    Syscall(SyscallName, SyscallState),
+    /// see [`CoroutineState::Complete`], the result is valid only if the flag is `true`.
+    Complete(bool, usize),
+    /// see [`CoroutineState::Error`], the message is passed as the pointer and length.
+    Error(*const u8, usize),
+    /// see [`CoroutineState::Cancelled`].
+    Cancelled,
+}
+
+impl From<CoroutineState<(), Option<usize>>> for RawCoroutineState {
+    fn from(state: CoroutineState<(), Option<usize>>) -> Self {
+        match state {
+            CoroutineState::Ready => Self::Ready,
+            CoroutineState::Running => Self::Running,
+            CoroutineState::Suspend((), timestamp) => Self::Suspend(timestamp),
+            CoroutineState::Syscall((), syscall, state) => Self::Syscall(syscall, state),
+            CoroutineState::Complete(result) => {
+                Self::Complete(result.is_some(), result.unwrap_or_default())
+            }
+            CoroutineState::Error(message) => Self::Error(message.as_ptr(), message.len()),
+            CoroutineState::Cancelled => Self::Cancelled,
+        }
+    }
+}
+
+impl RawCoroutineState {
+    /// Convert back to [`CoroutineState`].
+    ///
+    /// # Safety
+    /// the message of [`RawCoroutineState::Error`] must point to a static str.
+    #[must_use]
+    pub unsafe fn into_state(self) -> CoroutineState<(), Option<usize>> {
+        match self {
+            Self::Ready => CoroutineState::Ready,
+            Self::Running => CoroutineState::Running,
+            Self::Suspend(timestamp) => CoroutineState::Suspend((), timestamp),
+            Self::Syscall(syscall, state) => CoroutineState::Syscall((), syscall, state),
+            Self::Complete(valid, result) => CoroutineState::Complete(valid.then_some(result)),
+            Self::Error(ptr, len) => CoroutineState::Error(raw_str(ptr, len)),
+            Self::Cancelled => CoroutineState::Cancelled,
+        }
+    }
+}
+
+unsafe fn raw_str<'s>(ptr: *const u8, len: usize) -> &'s str {
+    std::str::from_utf8_unchecked(std::slice::from_raw_parts(ptr, len))
+}
+
+/// A runtime-wide listener made of `extern "C"` callbacks, it's used to add listeners
+/// across the dylib boundary, where the trait objects of the two copies of this crate are
+/// not compatible. The strs are passed as the pointers and lengths.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ListenerTable {
+    /// The opaque data passed to every callback.
+    pub data: usize,
+    /// see [`Listener::on_create`].
+    pub on_create: extern "C" fn(usize, *const u8, usize, usize),
+    /// see [`Listener::on_state_changed`].
+    pub on_state_changed: extern "C" fn(usize, RawCoroutineState, RawCoroutineState),
+    /// see [`Listener::on_ready`].
+    pub on_ready: extern "C" fn(usize, RawCoroutineState),
+    /// see [`Listener::on_running`].
+    pub on_running: extern "C" fn(usize, RawCoroutineState),
+    /// see [`Listener::on_suspend`].
+    pub on_suspend: extern "C" fn(usize, RawCoroutineState),
+    /// see [`Listener::on_syscall`].
+    pub on_syscall: extern "C" fn(usize, RawCoroutineState),
+    /// see [`Listener::on_complete`], the result is valid only if the flag is `true`.
+    pub on_complete: extern "C" fn(usize, RawCoroutineState, bool, usize),
+    /// see [`Listener::on_error`].
+    pub on_error: extern "C" fn(usize, RawCoroutineState, *const u8, usize),
+    /// see [`Listener::on_stack_usage`].
+    pub on_stack_usage: extern "C" fn(usize, usize, usize),
+    /// see [`Listener::on_drop`].
+    pub on_drop: extern "C" fn(usize, *const u8, usize),
+}
+
+thread_local! {
+    //跨越dylib时无法访问协程本地变量，每次回调都使用空的
+    static EMPTY_LOCAL: CoroutineLocal<'static> = CoroutineLocal::default();
+}
+
+fn with_empty_local(f: impl FnOnce(&CoroutineLocal)) {
+    EMPTY_LOCAL.with(|local| {
+        //不能展开到extern "C"函数之外
+        _ = crate::catch!(|| f(local), "invoke listener");
+        local.clear();
+    });
+}
+
+impl ListenerTable {
+    /// Create the callback table of the listener, the listener is leaked.
+    ///
+    /// The coroutine locals can't be accessed across the dylib boundary, the listener
+    /// always gets an empty local.
+    #[must_use]
+    #[allow(clippy::too_many_lines)]
+    pub fn new<L: Listener<(), Option<usize>> + Send + Sync + 'static>(listener: L) -> Self {
+        extern "C" fn on_create<L: Listener<(), Option<usize>>>(
+            data: usize,
+            ptr: *const u8,
+            len: usize,
+            stack_size: usize,
+        ) {
+            let listener = unsafe { &*(data as *const L) };
+            let name = unsafe { raw_str(ptr, len) };
+            with_empty_local(|local| listener.on_create(local, name, stack_size));
+        }
+        extern "C" fn on_state_changed<L: Listener<(), Option<usize>>>(
+            data: usize,
+            old_state: RawCoroutineState,
+            new_state: RawCoroutineState,
+        ) {
+            let listener = unsafe { &*(data as *const L) };
+            let (old_state, new_state) =
+                unsafe { (old_state.into_state(), new_state.into_state()) };
+            with_empty_local(|local| listener.on_state_changed(local, old_state, new_state));
+        }
+        extern "C" fn on_ready<L: Listener<(), Option<usize>>>(
+            data: usize,
+            old_state: RawCoroutineState,
+        ) {
+            let listener = unsafe { &*(data as *const L) };
+            let old_state = unsafe { old_state.into_state() };
+            with_empty_local(|local| listener.on_ready(local, old_state));
+        }
+        extern "C" fn on_running<L: Listener<(), Option<usize>>>(
+            data: usize,
+            old_state: RawCoroutineState,
+        ) {
+            let listener = unsafe { &*(data as *const L) };
+            let old_state = unsafe { old_state.into_state() };
+            with_empty_local(|local| listener.on_running(local, old_state));
+        }
+        extern "C" fn on_suspend<L: Listener<(), Option<usize>>>(
+            data: usize,
+            old_state: RawCoroutineState,
+        ) {
+            let listener = unsafe { &*(data as *const L) };
+            let old_state = unsafe { old_state.into_state() };
+            with_empty_local(|local| listener.on_suspend(local, old_state));
+        }
+        extern "C" fn on_syscall<L: Listener<(), Option<usize>>>(
+            data: usize,
+            old_state: RawCoroutineState,
+        ) {
+            let listener = unsafe { &*(data as *const L) };
+            let old_state = unsafe { old_state.into_state() };
+            with_empty_local(|local| listener.on_syscall(local, old_state));
+        }
+        extern "C" fn on_complete<L: Listener<(), Option<usize>>>(
+            data: usize,
+            old_state: RawCoroutineState,
+            valid: bool,
+            result: usize,
+        ) {
+            let listener = unsafe { &*(data as *const L) };
+            let old_state = unsafe { old_state.into_state() };
+            with_empty_local(|local| {
+                listener.on_complete(local, old_state, valid.then_some(result));
+            });
+        }
+        extern "C" fn on_error<L: Listener<(), Option<usize>>>(
+            data: usize,
+            old_state: RawCoroutineState,
+            ptr: *const u8,
+            len: usize,
+        ) {
+            let listener = unsafe { &*(data as *const L) };
+            let old_state = unsafe { old_state.into_state() };
+            let message = unsafe { raw_str(ptr, len) };
+            with_empty_local(|local| listener.on_error(local, old_state, message));
+        }
+        extern "C" fn on_stack_usage<L: Listener<(), Option<usize>>>(
+            data: usize,
+            stack_size: usize,
+            peak: usize,
+        ) {
+            let listener = unsafe { &*(data as *const L) };
+            with_empty_local(|local| listener.on_stack_usage(local, stack_size, peak));
+        }
+        extern "C" fn on_drop<L: Listener<(), Option<usize>>>(
+            data: usize,
+            ptr: *const u8,
+            len: usize,
+        ) {
+            let listener = unsafe { &*(data as *const L) };
+            let name = unsafe { raw_str(ptr, len) };
+            with_empty_local(|local| listener.on_drop(local, name));
+        }
+        ListenerTable {
+            data: std::ptr::from_ref(Box::leak(Box::new(listener))) as usize,
+            on_create: on_create::<L>,
+            on_state_changed: on_state_changed::<L>,
+            on_ready: on_ready::<L>,
+            on_running: on_running::<L>,
+            on_suspend: on_suspend::<L>,
+            on_syscall: on_syscall::<L>,
+            on_complete: on_complete::<L>,
+            on_error: on_error::<L>,
+            on_stack_usage: on_stack_usage::<L>,
+            on_drop: on_drop::<L>,
+        }
+    }
+}
+
+impl Listener<(), Option<usize>> for ListenerTable {
+    fn on_create(&self, _: &CoroutineLocal, name: &str, stack_size: usize) {
+        (self.on_create)(self.data, name.as_ptr(), name.len(), stack_size);
+    }
+
+    fn on_state_changed(
+        &self,
+        _: &CoroutineLocal,
+        old_state: CoroutineState<(), Option<usize>>,
+        new_state: CoroutineState<(), Option<usize>>,
+    ) {
+        (self.on_state_changed)(self.data, old_state.into(), new_state.into());
+    }
+
+    fn on_ready(&self, _: &CoroutineLocal, old_state: CoroutineState<(), Option<usize>>) {
+        (self.on_ready)(self.data, old_state.into());
+    }
+
+    fn on_running(&self, _: &CoroutineLocal, old_state: CoroutineState<(), Option<usize>>) {
+        (self.on_running)(self.data, old_state.into());
+    }
+
+    fn on_suspend(&self, _: &CoroutineLocal, old_state: CoroutineState<(), Option<usize>>) {
+        (self.on_suspend)(self.data, old_state.into());
+    }
+
+    fn on_syscall(&self, _: &CoroutineLocal, old_state: CoroutineState<(), Option<usize>>) {
+        (self.on_syscall)(self.data, old_state.into());
+    }
+
+    fn on_complete(
+        &self,
+        _: &CoroutineLocal,
+        old_state: CoroutineState<(), Option<usize>>,
+        result: Option<usize>,
+    ) {
+        (self.on_complete)(
+            self.data,
+            old_state.into(),
+            result.is_some(),
+            result.unwrap_or_default(),
+        );
+    }
+
+    fn on_error(
+        &self,
+        _: &CoroutineLocal,
+        old_state: CoroutineState<(), Option<usize>>,
+        message: &str,
+    ) {
+        (self.on_error)(self.data, old_state.into(), message.as_ptr(), message.len());
+    }
+
+    fn on_stack_usage(&self, _: &CoroutineLocal, stack_size: usize, peak: usize) {
+        (self.on_stack_usage)(self.data, stack_size, peak);
+    }
+
+    fn on_drop(&self, _: &CoroutineLocal, name: &str) {
+        (self.on_drop)(self.data, name.as_ptr(), name.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Default)]
+    struct CountListener(AtomicUsize);
+
+    impl Listener<(), Option<usize>> for &'static CountListener {
+        fn on_state_changed(
+            &self,
+            _: &CoroutineLocal,
+            old_state: CoroutineState<(), Option<usize>>,
+            new_state: CoroutineState<(), Option<usize>>,
+        ) {
+            assert_eq!(CoroutineState::Running, old_state);
+            assert_eq!(CoroutineState::Error("test error"), new_state);
+            _ = self.0.fetch_add(1, Ordering::Release);
+        }
+
+        fn on_complete(
+            &self,
+            _: &CoroutineLocal,
+            _: CoroutineState<(), Option<usize>>,
+            result: Option<usize>,
+        ) {
+            assert_eq!(Some(1), result);
+            _ = self.0.fetch_add(1, Ordering::Release);
+        }
+
+        fn on_drop(&self, _: &CoroutineLocal, name: &str) {
+            assert_eq!("test", name);
+            _ = self.0.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    #[test]
+    fn test_listener_table() {
+        let listener: &'static CountListener = Box::leak(Box::default());
+        let table = ListenerTable::new(listener);
+        let local = CoroutineLocal::default();
+        table.on_state_changed(
+            &local,
+            CoroutineState::Running,
+            CoroutineState::Error("test error"),
+        );
+        table.on_complete(&local, CoroutineState::Running, Some(1));
+        table.on_drop(&local, "test");
+        assert_eq!(3, listener.0.load(Ordering::Acquire));
+    }
+}
@@ -1,9 +1,18 @@
+use crate::co_pool::task::Task;
+use crate::common::beans::BeanFactory;
+use crate::common::constants::{TaskStatus, COROUTINE_GLOBAL_QUEUE_BEAN, TASK_GLOBAL_QUEUE_BEAN};
+use crate::common::ordered_work_steal::OrderedWorkStealQueue;
 use crate::config::Config;
+use crate::coroutine::id::CoroutineId;
+use crate::coroutine::listener::Listener;
 use crate::coroutine::suspender::Suspender;
+use crate::dump::Dump;
 use crate::net::event_loop::EventLoop;
 use crate::net::join::JoinHandle;
+use crate::scheduler::SchedulableCoroutine;
 use crate::{error, info};
 use once_cell::sync::OnceCell;
+use std::any::Any;
 use std::collections::VecDeque;
 use std::ffi::{c_int, c_longlong};
 use std::io::{Error, ErrorKind};
@@ -47,8 +56,21 @@ pub mod event_loop;
 /// Task join abstraction and impl.
 pub mod join;
 
+/// Runtime-wide listeners across the dylib boundary.
+pub mod listener;
+
 static INSTANCE: OnceCell<EventLoops> = OnceCell::new();
 
+/// A type for runtime-wide listeners.
+pub type EventLoopListener = dyn Listener<(), Option<usize>> + Send + Sync;
+
+/// The listeners added to the schedulers of all event-loops.
+static LISTENERS: Mutex<Vec<&'static EventLoopListener>> = Mutex::new(Vec::new());
+
+fn listeners() -> Vec<&'static EventLoopListener> {
+    LISTENERS.lock().expect("lock listeners failed").clone()
+}
+
 /// The manager for `EventLoop`.
 #[repr(C)]
 #[derive(Debug)]
@@ -68,14 +90,18 @@ impl EventLoops {
         _ = INSTANCE.get_or_init(|| {
             #[cfg(feature = "ci")]
             crate::common::ci::init();
-            let loops = Self::new(
-                config.event_loop_size(),
-                config.stack_size(),
-                config.min_size(),
-                config.max_size(),
-                config.keep_alive_time(),
+            crate::coroutine::set_stack_canary(config.stack_canary());
+            let aging_threshold = match config.aging_threshold() {
+                0 => None,
+                threshold => Some(Duration::from_nanos(threshold)),
+            };
+            BeanFactory::get_or_default::<OrderedWorkStealQueue<Task>>(TASK_GLOBAL_QUEUE_BEAN)
+                .set_aging_threshold(aging_threshold);
+            BeanFactory::get_or_default::<OrderedWorkStealQueue<SchedulableCoroutine>>(
+                COROUTINE_GLOBAL_QUEUE_BEAN,
             )
-            .expect("init default EventLoops failed !");
+            .set_aging_threshold(aging_threshold);
+            let loops = Self::with_config(config).expect("init default EventLoops failed !");
             #[cfg(feature = "log")]
             let _ = tracing_subscriber::fmt()
                 .with_thread_names(true)
@@ -98,17 +124,27 @@ impl EventLoops {
         max_size: usize,
         keep_alive_time: u64,
     ) -> std::io::Result<Self> {
+        Self::with_config(&Config::new(
+            event_loop_size,
+            stack_size,
+            min_size,
+            max_size,
+            keep_alive_time,
+            0,
+            0,
+            true,
+        ))
+    }
+
+    fn with_config(config: &Config) -> std::io::Result<Self> {
         let shared_stop = Arc::new((Mutex::new(AtomicUsize::new(0)), Condvar::new()));
         let mut loops = VecDeque::new();
-        for i in 0..event_loop_size {
+        for i in 0..config.event_loop_size() {
             loops.push_back(
-                EventLoop::new(
+                EventLoop::with_config(
                     format!("open-coroutine-event-loop-{i}"),
                     i,
-                    stack_size,
-                    min_size,
-                    max_size,
-                    keep_alive_time,
+                    config,
                     shared_stop.clone(),
                 )?
                 .start()?,
@@ -121,6 +157,35 @@ impl EventLoops {
         })
     }
 
+    /// Add a listener to the schedulers of all event-loops, it should be called before
+    /// [`EventLoops::init`], otherwise it won't take effect.
+    ///
+    /// # Errors
+    /// if the `EventLoops` has been initialized.
+    pub fn add_listener(
+        listener: impl Listener<(), Option<usize>> + Send + Sync + 'static,
+    ) -> std::io::Result<()> {
+        Self::add_raw_listener(Box::leak(Box::new(listener)))
+    }
+
+    /// see `add_listener`.
+    ///
+    /// # Errors
+    /// if the `EventLoops` has been initialized.
+    pub fn add_raw_listener(listener: &'static EventLoopListener) -> std::io::Result<()> {
+        let mut listeners = LISTENERS
+            .lock()
+            .map_err(|e| Error::new(ErrorKind::Other, format!("{e}")))?;
+        if INSTANCE.get().is_some() {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "The listener should be added before EventLoops init !",
+            ));
+        }
+        listeners.push(listener);
+        Ok(())
+    }
+
     fn round_robin() -> &'static Arc<EventLoop<'static>> {
         let instance = INSTANCE.get().expect("EventLoops not init !");
         let index = instance.index.fetch_add(1, Ordering::Release) % instance.loops.len();
@@ -139,30 +204,118 @@ impl EventLoops {
     ///
     /// Allow multiple threads to concurrently submit task to the pool,
     /// but only allow one thread to execute scheduling.
-    pub fn submit_task(
+    ///
+    /// # Errors
+    /// if the task was rejected, see
+    /// [`CoroutinePool::submit_task`](crate::co_pool::CoroutinePool::submit_task).
+    pub fn submit_task<R: Any + Send>(
         name: Option<String>,
-        func: impl FnOnce(Option<usize>) -> Option<usize> + 'static,
+        func: impl FnOnce(Option<usize>) -> R + 'static,
         param: Option<usize>,
         priority: Option<c_longlong>,
-    ) -> JoinHandle {
+    ) -> std::io::Result<JoinHandle> {
         let event_loop = Self::round_robin();
         event_loop
             .submit_task(name, func, param, priority)
-            .map_or_else(
-                |_| JoinHandle::err(event_loop),
-                |n| JoinHandle::new(event_loop, n.as_str()),
-            )
+            .map(|n| JoinHandle::new(event_loop, n.as_str()))
+    }
+
+    /// Submit a new task to event-loop which runs at the absolute `timestamp` in nanoseconds,
+    /// see [`CoroutinePool::schedule_at`](crate::co_pool::CoroutinePool::schedule_at).
+    ///
+    /// # Errors
+    /// if the event-loop is stopping or stopped.
+    pub fn schedule_at<R: Any + Send>(
+        name: Option<String>,
+        func: impl FnOnce(Option<usize>) -> R + 'static,
+        param: Option<usize>,
+        priority: Option<c_longlong>,
+        timestamp: u64,
+    ) -> std::io::Result<JoinHandle> {
+        let event_loop = Self::round_robin();
+        event_loop
+            .schedule_at(name, func, param, priority, timestamp)
+            .map(|n| JoinHandle::new(event_loop, n.as_str()))
+    }
+
+    /// Submit a new task to event-loop which runs every `period`, the returned handle can
+    /// cancel the future runs, see
+    /// [`CoroutinePool::schedule_with_fixed_rate`](crate::co_pool::CoroutinePool::schedule_with_fixed_rate).
+    ///
+    /// # Errors
+    /// if the event-loop is stopping or stopped, or the `period` is zero.
+    pub fn schedule_with_fixed_rate(
+        name: Option<String>,
+        func: impl FnMut(Option<usize>) + 'static,
+        param: Option<usize>,
+        priority: Option<c_longlong>,
+        initial_delay: Duration,
+        period: Duration,
+    ) -> std::io::Result<JoinHandle> {
+        let event_loop = Self::round_robin();
+        event_loop
+            .schedule_with_fixed_rate(name, func, param, priority, initial_delay, period)
+            .map(|n| JoinHandle::new(event_loop, n.as_str()))
+    }
+
+    /// Submit a new task to event-loop which runs again `delay` after the previous run
+    /// finished, the returned handle can cancel the future runs, see
+    /// [`CoroutinePool::schedule_with_fixed_delay`](crate::co_pool::CoroutinePool::schedule_with_fixed_delay).
+    ///
+    /// # Errors
+    /// if the event-loop is stopping or stopped, or the `delay` is zero.
+    pub fn schedule_with_fixed_delay(
+        name: Option<String>,
+        func: impl FnMut(Option<usize>) + 'static,
+        param: Option<usize>,
+        priority: Option<c_longlong>,
+        initial_delay: Duration,
+        delay: Duration,
+    ) -> std::io::Result<JoinHandle> {
+        let event_loop = Self::round_robin();
+        event_loop
+            .schedule_with_fixed_delay(name, func, param, priority, initial_delay, delay)
+            .map(|n| JoinHandle::new(event_loop, n.as_str()))
+    }
+
+    /// Submit new tasks with the same priority to event-loop in bulk,
+    /// see [`CoroutinePool::submit_tasks`](crate::co_pool::CoroutinePool::submit_tasks).
+    ///
+    /// # Errors
+    /// if the event-loop is stopping or stopped.
+    pub fn submit_tasks<R: Any + Send, F: FnOnce(Option<usize>) -> R + 'static>(
+        tasks: impl IntoIterator<Item = (F, Option<usize>)>,
+        priority: Option<c_longlong>,
+    ) -> std::io::Result<Vec<JoinHandle>> {
+        let event_loop = Self::round_robin();
+        Ok(event_loop
+            .submit_tasks(tasks, priority)?
+            .iter()
+            .map(|name| JoinHandle::new(event_loop, name))
+            .collect())
+    }
+
+    /// Submit a task returned by [`EventLoops::shutdown_now`] to event-loop, see
+    /// [`CoroutinePool::resubmit_task`](crate::co_pool::CoroutinePool::resubmit_task).
+    ///
+    /// # Errors
+    /// if the task was rejected.
+    pub fn resubmit_task(task: Task<'static>) -> std::io::Result<JoinHandle> {
+        let event_loop = Self::round_robin();
+        event_loop
+            .resubmit_task(task)
+            .map(|n| JoinHandle::new(event_loop, n.as_str()))
     }
 
     /// Submit a new coroutine to event-loop.
     ///
     /// Allow multiple threads to concurrently submit coroutine to the pool,
     /// but only allow one thread to execute scheduling.
-    pub fn submit_co(
-        f: impl FnOnce(&Suspender<(), ()>, ()) -> Option<usize> + 'static,
+    pub fn submit_co<R: Any + Send>(
+        f: impl FnOnce(&Suspender<(), ()>, ()) -> R + 'static,
         stack_size: Option<usize>,
         priority: Option<c_longlong>,
-    ) -> std::io::Result<()> {
+    ) -> std::io::Result<CoroutineId> {
         Self::round_robin().submit_co(f, stack_size, priority)
     }
 
@@ -230,6 +383,59 @@ impl EventLoops {
         Ok(())
     }
 
+    /// Get the status of the task with the given `task_name`, see
+    /// [`CoroutinePool::task_status`](crate::co_pool::CoroutinePool::task_status).
+    #[must_use]
+    pub fn task_status(task_name: &str) -> Option<TaskStatus> {
+        INSTANCE
+            .get()?
+            .loops
+            .iter()
+            .find_map(|event_loop| event_loop.task_status(task_name))
+    }
+
+    /// List the pending tasks of each `EventLoop` with their priorities, see
+    /// [`CoroutinePool::pending_tasks`](crate::co_pool::CoroutinePool::pending_tasks).
+    #[must_use]
+    pub fn pending_tasks() -> Vec<(String, Vec<(String, c_longlong)>)> {
+        INSTANCE.get().map_or_else(Vec::new, |instance| {
+            instance
+                .loops
+                .iter()
+                .map(|event_loop| (String::from(event_loop.name()), event_loop.pending_tasks()))
+                .collect()
+        })
+    }
+
+    /// Dump the coroutines of all `EventLoop`, the result can be printed as a text report.
+    ///
+    /// Call [`crate::dump::set_dump_enabled`] before scheduling, and
+    /// [`crate::dump::set_capture_backtrace`] to capture the backtraces at the
+    /// suspension points.
+    #[must_use]
+    pub fn dump() -> Dump {
+        INSTANCE.get().map_or_else(Dump::default, |instance| Dump {
+            schedulers: instance.loops.iter().map(|i| i.dump()).collect(),
+        })
+    }
+
+    /// Stop all `EventLoop` forcefully, returns the tasks which have never started, see
+    /// [`CoroutinePool::shutdown_now`](crate::co_pool::CoroutinePool::shutdown_now).
+    ///
+    /// Call [`EventLoops::stop`] to wait for the cancelled tasks to unwind.
+    ///
+    /// # Errors
+    /// if change state fails.
+    pub fn shutdown_now() -> std::io::Result<Vec<Task<'static>>> {
+        let mut tasks = Vec::new();
+        if let Some(instance) = INSTANCE.get() {
+            for i in &instance.loops {
+                tasks.extend(i.shutdown_now()?);
+            }
+        }
+        Ok(tasks)
+    }
+
     /// Stop all `EventLoop`.
     pub fn stop(wait_time: Duration) -> std::io::Result<()> {
         if let Some(instance) = INSTANCE.get() {
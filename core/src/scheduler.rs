@@ -1,16 +1,29 @@
 use crate::common::beans::BeanFactory;
+use crate::common::constants::SLICE;
 use crate::common::constants::{CoroutineState, SyscallState};
-use crate::common::ordered_work_steal::{OrderedLocalQueue, OrderedWorkStealQueue};
+use crate::common::histogram::Histogram;
+use crate::common::join_error::{JoinError, JoinErrorKind};
+use crate::common::ordered_work_steal::{Ordered, OrderedWorkStealQueue, DEFAULT_PRECEDENCE};
+use crate::common::ready_queue::{FifoQueue, LifoSlotQueue, ReadyQueue};
+use crate::common::timer_wheel::{TimerKey, TimerWheel};
 use crate::common::{get_timeout_time, now};
+use crate::coroutine::id::CoroutineId;
 use crate::coroutine::listener::Listener;
+use crate::coroutine::local::CoroutineLocal;
 use crate::coroutine::suspender::Suspender;
 use crate::coroutine::Coroutine;
+use crate::dump::{CoroutineDump, SchedulerDump};
 use crate::{co, impl_current_for, impl_display_by_debug, impl_for_named};
-use dashmap::DashMap;
-use std::collections::{BinaryHeap, VecDeque};
+use dashmap::{DashMap, DashSet};
+use once_cell::sync::Lazy;
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::ffi::c_longlong;
+use std::hash::Hash;
 use std::io::{Error, ErrorKind};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
 use std::time::Duration;
 
 /// A type for Scheduler.
@@ -22,59 +35,165 @@ pub type SchedulableCoroutine<'s> = Coroutine<'s, (), (), Option<usize>>;
 /// A type for Scheduler.
 pub type SchedulableSuspender<'s> = Suspender<'s, (), ()>;
 
+/// A result and the time when it's stored.
+pub(crate) type StoredResult = (u64, Result<Box<dyn Any + Send>, JoinError>);
+
+/// The names of coroutines that are asked to cancel.
+static CANCEL_COROUTINES: Lazy<DashSet<String>> = Lazy::new(DashSet::new);
+
+/// The scheduler key and the id of the alive coroutines, keyed by the coroutine name.
+static COROUTINE_OWNERS: Lazy<DashMap<String, (usize, CoroutineId)>> = Lazy::new(DashMap::new);
+
+/// The cancel requests not yet handled by their schedulers, keyed by the scheduler key.
+static CANCEL_REQUESTS: Lazy<DashMap<usize, Vec<(CoroutineId, String)>>> = Lazy::new(DashMap::new);
+
+/// The scheduler names may be duplicated, so the schedulers are keyed by a unique number.
+static SCHEDULER_KEY: AtomicUsize = AtomicUsize::new(0);
+
+/// The coroutines which are allowed to continue, see [`Unparker`].
+static UNPARKED: Lazy<DashSet<CoroutineId>> = Lazy::new(DashSet::new);
+
+/// The key of [`Runtime`] in the coroutine local.
+const RUNTIME: &str = "open_coroutine_runtime";
+
+/// The weights of priorities in `[-20, 19]`, the same as the nice values of linux CFS,
+/// a coroutine with 1 lower priority value will get about 25% more CPU time.
+const WEIGHTS: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110, 87,
+    70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+/// The policies used by [`Scheduler`] to pick the next ready coroutine.
 #[repr(C)]
-#[derive(Debug)]
-struct SuspendItem<'s> {
-    timestamp: u64,
-    coroutine: SchedulableCoroutine<'s>,
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum SchedulePolicy {
+    /// Always pick the coroutine with the highest priority, FIFO within a priority.
+    #[default]
+    Fifo,
+    /// Always pick the coroutine with the least virtual runtime, which is the CPU time
+    /// weighted by its priority.
+    ///
+    /// The ready coroutines are taken out of the work-stealing queue to be sorted,
+    /// so they can no longer be stolen by other schedulers.
+    Fair,
 }
 
-impl PartialEq<Self> for SuspendItem<'_> {
-    fn eq(&self, other: &Self) -> bool {
-        self.timestamp.eq(&other.timestamp)
-    }
+/// The kinds of the ready queue used by [`Scheduler`], a custom [`ReadyQueue`] can be
+/// passed to [`Scheduler::with_ready_queue`].
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub enum ReadyQueueKind {
+    /// The local queue of the global work-stealing queue, ordered by priority.
+    #[default]
+    WorkSteal,
+    /// The work-stealing queue with a LIFO slot, the just woken coroutine runs next.
+    LifoSlot,
+    /// A strict FIFO queue which ignores the priorities, and the coroutines are never
+    /// stolen by other schedulers.
+    StrictFifo,
 }
 
-impl Eq for SuspendItem<'_> {}
+impl ReadyQueueKind {
+    /// Create the ready queue of this kind.
+    #[must_use]
+    pub fn create<'s>(self) -> Box<dyn ReadyQueue<SchedulableCoroutine<'s>> + 's> {
+        match self {
+            ReadyQueueKind::WorkSteal => Box::new(Self::work_steal()),
+            ReadyQueueKind::LifoSlot => Box::new(LifoSlotQueue::new(Self::work_steal())),
+            ReadyQueueKind::StrictFifo => Box::new(FifoQueue::default()),
+        }
+    }
 
-impl PartialOrd<Self> for SuspendItem<'_> {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+    fn work_steal<'s>() -> impl ReadyQueue<SchedulableCoroutine<'s>> + 's {
+        BeanFactory::get_or_default::<OrderedWorkStealQueue<SchedulableCoroutine>>(
+            crate::common::constants::COROUTINE_GLOBAL_QUEUE_BEAN,
+        )
+        .local_queue()
     }
 }
 
-impl Ord for SuspendItem<'_> {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        // BinaryHeap defaults to a large top heap, but we need a small top heap
-        other.timestamp.cmp(&self.timestamp)
-    }
+/// The CPU time accounting of a coroutine.
+#[derive(Debug, Default)]
+struct Runtime {
+    started: Option<u64>,
+    total: u64,
+    charged: u64,
+    vruntime: u64,
 }
 
+/// Accumulates the CPU time between `on_running` and `on_suspend`/`on_syscall`,
+/// it does nothing after the scheduler leaves [`SchedulePolicy::Fair`].
 #[repr(C)]
-#[derive(Debug)]
-struct SyscallSuspendItem<'s> {
-    timestamp: u64,
-    co_name: &'s str,
+#[derive(Debug, Default)]
+struct RuntimeListener(AtomicBool);
+
+impl RuntimeListener {
+    fn stop(local: &CoroutineLocal) {
+        if let Some(runtime) = local.get_mut::<Runtime>(RUNTIME) {
+            if let Some(started) = runtime.started.take() {
+                runtime.total += now().saturating_sub(started);
+            }
+        }
+    }
 }
 
-impl PartialEq<Self> for SyscallSuspendItem<'_> {
-    fn eq(&self, other: &Self) -> bool {
-        self.timestamp.eq(&other.timestamp)
+impl Listener<(), Option<usize>> for RuntimeListener {
+    fn on_running(&self, local: &CoroutineLocal, _: SchedulableCoroutineState) {
+        if !self.0.load(Ordering::Acquire) {
+            return;
+        }
+        if let Some(runtime) = local.get_mut::<Runtime>(RUNTIME) {
+            runtime.started = Some(now());
+        } else {
+            _ = local.put(
+                RUNTIME,
+                Runtime {
+                    started: Some(now()),
+                    ..Runtime::default()
+                },
+            );
+        }
     }
-}
 
-impl Eq for SyscallSuspendItem<'_> {}
+    fn on_suspend(&self, local: &CoroutineLocal, _: SchedulableCoroutineState) {
+        if self.0.load(Ordering::Acquire) {
+            Self::stop(local);
+        }
+    }
 
-impl PartialOrd<Self> for SyscallSuspendItem<'_> {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+    fn on_syscall(&self, local: &CoroutineLocal, _: SchedulableCoroutineState) {
+        if self.0.load(Ordering::Acquire) {
+            Self::stop(local);
+        }
     }
 }
 
-impl Ord for SyscallSuspendItem<'_> {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        // BinaryHeap defaults to a large top heap, but we need a small top heap
-        other.timestamp.cmp(&self.timestamp)
+thread_local! {
+    /// The coroutine which is going to park until the timestamp.
+    static PARKING: Cell<Option<(CoroutineId, u64)>> = const { Cell::new(None) };
+}
+
+#[derive(Debug, Clone)]
+enum Parkable {
+    Coroutine(CoroutineId),
+    Thread(std::thread::Thread),
+}
+
+/// A handle to wake up a parked coroutine, it can be sent to any thread.
+///
+/// Like [`std::thread::Thread::unpark`], if the coroutine is not parked, the next
+/// [`Scheduler::park`] will return immediately.
+#[derive(Debug, Clone)]
+pub struct Unparker(Parkable);
+
+impl Unparker {
+    /// Move the parked coroutine back to the ready queue of its scheduler.
+    pub fn unpark(&self) {
+        match &self.0 {
+            Parkable::Coroutine(co_id) => _ = UNPARKED.insert(*co_id),
+            Parkable::Thread(thread) => thread.unpark(),
+        }
     }
 }
 
@@ -83,13 +202,31 @@ impl Ord for SyscallSuspendItem<'_> {
 #[derive(Debug)]
 pub struct Scheduler<'s> {
     name: String,
+    key: usize,
     stack_size: AtomicUsize,
     listeners: VecDeque<&'s dyn Listener<(), Option<usize>>>,
-    ready: OrderedLocalQueue<'s, SchedulableCoroutine<'s>>,
-    suspend: BinaryHeap<SuspendItem<'s>>,
-    syscall: DashMap<&'s str, SchedulableCoroutine<'s>>,
-    syscall_suspend: BinaryHeap<SyscallSuspendItem<'s>>,
-    results: DashMap<&'s str, Result<Option<usize>, &'s str>>,
+    ready: Box<dyn ReadyQueue<SchedulableCoroutine<'s>> + 's>,
+    policy: SchedulePolicy,
+    //按(虚拟运行时间, 序号)排序的就绪协程
+    fair: BTreeMap<(u64, u64), SchedulableCoroutine<'s>>,
+    fair_seq: u64,
+    min_vruntime: u64,
+    runtime_listener: Option<&'s RuntimeListener>,
+    suspend: TimerWheel<SchedulableCoroutine<'s>>,
+    //挂起在时间轮中的协程
+    suspended: HashMap<CoroutineId, TimerKey>,
+    syscall: DashMap<CoroutineId, (SchedulableCoroutine<'s>, Option<TimerKey>)>,
+    syscall_suspend: Mutex<TimerWheel<CoroutineId>>,
+    //被park的协程，仍然挂起在时间轮中
+    parked: HashMap<CoroutineId, TimerKey>,
+    //协程结果及其完成时间
+    results: DashMap<CoroutineId, StoredResult>,
+    //结果不再需要的协程
+    detached: DashSet<CoroutineId>,
+    //未取走的结果的保留时间，单位ns
+    result_ttl: AtomicU64,
+    next_expire: AtomicU64,
+    stack_histogram: Histogram,
 }
 
 impl Default for Scheduler<'_> {
@@ -137,18 +274,37 @@ impl<'s> Scheduler<'s> {
     /// Creates a new scheduler.
     #[must_use]
     pub fn new(name: String, stack_size: usize) -> Self {
+        Self::with_ready_queue(name, stack_size, ReadyQueueKind::default().create())
+    }
+
+    /// Creates a new scheduler with the `ready` queue.
+    #[must_use]
+    pub fn with_ready_queue(
+        name: String,
+        stack_size: usize,
+        ready: Box<dyn ReadyQueue<SchedulableCoroutine<'s>> + 's>,
+    ) -> Self {
         Scheduler {
             name,
+            key: SCHEDULER_KEY.fetch_add(1, Ordering::Relaxed),
             stack_size: AtomicUsize::new(stack_size),
             listeners: VecDeque::new(),
-            ready: BeanFactory::get_or_default::<OrderedWorkStealQueue<SchedulableCoroutine>>(
-                crate::common::constants::COROUTINE_GLOBAL_QUEUE_BEAN,
-            )
-            .local_queue(),
-            suspend: BinaryHeap::default(),
+            ready,
+            policy: SchedulePolicy::default(),
+            fair: BTreeMap::new(),
+            fair_seq: 0,
+            min_vruntime: 0,
+            runtime_listener: None,
+            suspend: TimerWheel::new(now()),
+            suspended: HashMap::new(),
             syscall: DashMap::default(),
-            syscall_suspend: BinaryHeap::default(),
+            syscall_suspend: Mutex::new(TimerWheel::new(now())),
+            parked: HashMap::new(),
             results: DashMap::default(),
+            detached: DashSet::default(),
+            result_ttl: AtomicU64::new(u64::MAX),
+            next_expire: AtomicU64::new(0),
+            stack_histogram: Histogram::default(),
         }
     }
 
@@ -163,30 +319,89 @@ impl<'s> Scheduler<'s> {
         self.stack_size.load(Ordering::Acquire)
     }
 
+    /// Get the policy used to pick the next ready coroutine.
+    pub fn policy(&self) -> SchedulePolicy {
+        self.policy
+    }
+
+    /// Set the policy used to pick the next ready coroutine, the CPU time of coroutines is
+    /// only tracked after [`SchedulePolicy::Fair`] is set, so it should be set before submitting.
+    pub fn set_policy(&mut self, policy: SchedulePolicy) {
+        if policy == self.policy {
+            return;
+        }
+        if SchedulePolicy::Fair == policy {
+            let listener = *self
+                .runtime_listener
+                .get_or_insert_with(|| Box::leak(Box::default()));
+            listener.0.store(true, Ordering::Release);
+            self.add_raw_listener(listener);
+        } else if let Some(listener) = self.runtime_listener {
+            //已提交的协程仍持有该监听器，只能让它失效
+            listener.0.store(false, Ordering::Release);
+            self.listeners
+                .retain(|l| !std::ptr::addr_eq(*l, std::ptr::from_ref(listener)));
+        }
+        for (_, coroutine) in std::mem::take(&mut self.fair) {
+            self.ready.push(coroutine);
+        }
+        self.policy = policy;
+    }
+
+    /// Replace the ready queue, the coroutines in the old queue are moved to the new one.
+    pub fn set_ready_queue(&mut self, ready: Box<dyn ReadyQueue<SchedulableCoroutine<'s>> + 's>) {
+        let old = std::mem::replace(&mut self.ready, ready);
+        while let Some(coroutine) = old.pop() {
+            self.ready.push(coroutine);
+        }
+    }
+
+    /// Get the histogram of the peak stack usage of the finished coroutines in this scheduler,
+    /// it only works when the stack canary is enabled,
+    /// see [`crate::coroutine::set_stack_canary`].
+    pub fn stack_histogram(&self) -> &Histogram {
+        &self.stack_histogram
+    }
+
     /// Submit a closure to create new coroutine, then the coroutine will be push into ready queue.
     ///
+    /// Returns the ID of the coroutine, it can be used to obtain the result by
+    /// [`Scheduler::try_get_co_result`].
+    ///
     /// Allow multiple threads to concurrently submit coroutine to the scheduler,
     /// but only allow one thread to execute scheduling.
     ///
     /// # Errors
     /// if create coroutine fails.
-    pub fn submit_co(
+    pub fn submit_co<R: Any + Send>(
         &self,
-        f: impl FnOnce(&Suspender<(), ()>, ()) -> Option<usize> + 'static,
+        f: impl FnOnce(&Suspender<(), ()>, ()) -> R + 'static,
         stack_size: Option<usize>,
         priority: Option<c_longlong>,
-    ) -> std::io::Result<()> {
-        self.submit_raw_co(co!(
+    ) -> std::io::Result<CoroutineId> {
+        let co = co!(
             Some(format!("{}@{}", self.name(), uuid::Uuid::new_v4())),
-            f,
+            move |suspender, param| {
+                let result = f(suspender, param);
+                if let Some(co) = SchedulableCoroutine::current() {
+                    co.set_result(Box::new(result));
+                }
+                None
+            },
             Some(stack_size.unwrap_or(self.stack_size())),
             priority
-        )?)
+        )?;
+        let co_id = co.id();
+        self.submit_raw_co(co).map(|()| co_id)
     }
 
     /// Add a listener to this scheduler.
     pub fn add_listener(&mut self, listener: impl Listener<(), Option<usize>> + 's) {
-        self.listeners.push_back(Box::leak(Box::new(listener)));
+        self.add_raw_listener(Box::leak(Box::new(listener)));
+    }
+
+    pub(crate) fn add_raw_listener(&mut self, listener: &'s dyn Listener<(), Option<usize>>) {
+        self.listeners.push_back(listener);
     }
 
     /// Submit a raw coroutine, then the coroutine will be push into ready queue.
@@ -197,10 +412,84 @@ impl<'s> Scheduler<'s> {
         for listener in self.listeners.clone() {
             co.add_raw_listener(listener);
         }
+        _ = COROUTINE_OWNERS.insert(String::from(co.name()), (self.key, co.id()));
+        CoroutineDump::record(self.name(), &co);
         self.ready.push(co);
         Ok(())
     }
 
+    /// Attempt to obtain the result of the finished coroutine with the given `co_id`,
+    /// use [`Box::downcast`] to get the typed value.
+    pub fn try_get_co_result(
+        &self,
+        co_id: CoroutineId,
+    ) -> Option<Result<Box<dyn Any + Send>, JoinError>> {
+        self.results.remove(&co_id).map(|(_, (_, r))| r)
+    }
+
+    /// Detach the coroutine with the given `co_id`, its result will be dropped when it
+    /// finishes instead of waiting to be retrieved.
+    pub fn detach_co(&self, co_id: CoroutineId) {
+        _ = self.detached.insert(co_id);
+        //协程可能已经结束
+        if self.results.remove(&co_id).is_some() {
+            _ = self.detached.remove(&co_id);
+        }
+    }
+
+    /// Set how long the results which are not retrieved are kept, it also applies to the
+    /// task results of [`CoroutinePool`](crate::co_pool::CoroutinePool), the expired results
+    /// are joined with [`JoinErrorKind::Expired`].
+    /// `result_ttl` has `ns` units, `u64::MAX` means forever, which is the default.
+    pub fn set_result_ttl(&self, result_ttl: u64) {
+        self.result_ttl.store(result_ttl, Ordering::Release);
+    }
+
+    /// Get how long the results which are not retrieved are kept.
+    /// Returns in `ns` units.
+    pub fn get_result_ttl(&self) -> u64 {
+        self.result_ttl.load(Ordering::Acquire)
+    }
+
+    /// Drop the results which are not retrieved in the TTL, the results are checked at
+    /// most once per half of the TTL, so they are kept for at least the TTL.
+    ///
+    /// The expired results are replaced by [`JoinErrorKind::Expired`], so the late joiners
+    /// won't wait forever.
+    pub(crate) fn expire_results<K: Eq + Hash>(
+        &self,
+        results: &DashMap<K, StoredResult>,
+        next_expire: &AtomicU64,
+    ) {
+        let ttl = self.get_result_ttl();
+        let now = now();
+        if u64::MAX == ttl || now < next_expire.load(Ordering::Acquire) {
+            return;
+        }
+        next_expire.store(now.saturating_add(ttl / 2), Ordering::Release);
+        for mut entry in results.iter_mut() {
+            let (completed, result) = entry.value_mut();
+            if now.saturating_sub(*completed) >= ttl
+                && !result
+                    .as_ref()
+                    .is_err_and(|e| JoinErrorKind::Expired == e.kind())
+            {
+                *result = Err(JoinError::new(JoinErrorKind::Expired));
+            }
+        }
+    }
+
+    fn complete(&self, co_id: CoroutineId, result: Result<Box<dyn Any + Send>, JoinError>) {
+        if self.detached.remove(&co_id).is_some() {
+            return;
+        }
+        self.expire_results(&self.results, &self.next_expire);
+        assert!(
+            self.results.insert(co_id, (now(), result)).is_none(),
+            "not consume result"
+        );
+    }
+
     /// Resume a coroutine from the syscall table to the ready queue,
     /// it's generally only required for framework level crates.
     ///
@@ -208,8 +497,12 @@ impl<'s> Scheduler<'s> {
     ///
     /// # Errors
     /// if change to ready fails.
-    pub fn try_resume(&self, co_name: &'s str) {
-        if let Some((_, co)) = self.syscall.remove(&co_name) {
+    pub fn try_resume(&self, co_id: CoroutineId) {
+        if let Some((_, (co, timer))) = self.syscall.remove(&co_id) {
+            if let Some(timer) = timer {
+                //系统调用提前完成，移除超时
+                _ = self.syscall_suspend().cancel(timer);
+            }
             match co.state() {
                 CoroutineState::Syscall(val, syscall, SyscallState::Suspend(_)) => {
                     co.syscall(val, syscall, SyscallState::Callback)
@@ -217,10 +510,87 @@ impl<'s> Scheduler<'s> {
                 }
                 _ => unreachable!("try_resume unexpect CoroutineState"),
             }
-            self.ready.push(co);
+            CoroutineDump::record(self.name(), &co);
+            self.ready.push_woken(co);
         }
     }
 
+    /// Dump the coroutines which scheduled by this scheduler.
+    #[must_use]
+    pub fn dump(&self) -> SchedulerDump {
+        SchedulerDump::collect(self.name())
+    }
+
+    /// Cancel a coroutine, the coroutine will unwind at its next suspension point.
+    ///
+    /// The cancel is not observed until then: a running coroutine that never suspends again
+    /// runs to completion. If the coroutine is already suspended, it will be woken up the next
+    /// time its scheduler checks the ready coroutines; if it's waiting in a hooked syscall, the
+    /// syscall will be interrupted. The unknown or finished coroutines are ignored.
+    pub fn try_cancel_coroutine(co_name: &str) {
+        //持有owner期间协程不会结束，不会遗留取消请求
+        let Some(owner) = COROUTINE_OWNERS.get(co_name) else {
+            return;
+        };
+        if CANCEL_COROUTINES.insert(String::from(co_name)) {
+            CANCEL_REQUESTS
+                .entry(owner.0)
+                .or_default()
+                .push((owner.1, String::from(co_name)));
+        }
+    }
+
+    /// Returns `true` if the coroutine is asked to cancel.
+    pub(crate) fn is_cancelling(co_name: &str) -> bool {
+        CANCEL_COROUTINES.contains(co_name)
+    }
+
+    /// Forget the cancel request of the coroutine.
+    pub(crate) fn clean_cancel(co_name: &str) {
+        _ = CANCEL_COROUTINES.remove(co_name);
+    }
+
+    /// Returns the [`Unparker`] of the current coroutine, or the current thread if
+    /// we are not in a coroutine.
+    #[must_use]
+    pub fn unparker() -> Unparker {
+        Unparker(SchedulableCoroutine::current().map_or_else(
+            || Parkable::Thread(std::thread::current()),
+            |co| Parkable::Coroutine(co.id()),
+        ))
+    }
+
+    /// Park the current coroutine until it's unparked by its [`Unparker`].
+    ///
+    /// If we are not in a coroutine, the current thread will be parked.
+    pub fn park() {
+        Self::park_until(u64::MAX, std::thread::park);
+    }
+
+    /// Park the current coroutine until it's unparked or the `dur` elapsed.
+    ///
+    /// If we are not in a coroutine, the current thread will be parked.
+    pub fn park_timeout(dur: Duration) {
+        Self::park_until(get_timeout_time(dur), || std::thread::park_timeout(dur));
+    }
+
+    fn park_until(timestamp: u64, park_thread: impl FnOnce()) {
+        let (Some(co), Some(suspender)) = (
+            SchedulableCoroutine::current(),
+            SchedulableSuspender::current(),
+        ) else {
+            park_thread();
+            return;
+        };
+        let co_id = co.id();
+        if UNPARKED.remove(&co_id).is_some() {
+            //已经被unpark过了
+            return;
+        }
+        PARKING.set(Some((co_id, timestamp)));
+        suspender.until(timestamp);
+    }
+
     /// Schedule the coroutines.
     ///
     /// Allow multiple threads to concurrently submit coroutine to the scheduler,
@@ -267,42 +637,73 @@ impl<'s> Scheduler<'s> {
             }
             self.check_ready()?;
             // schedule coroutines
-            if let Some(mut coroutine) = self.ready.pop() {
-                match coroutine.resume()? {
+            if let Some(mut coroutine) = self.pop_ready() {
+                // 不能从系统调用中展开栈
+                Suspender::<(), ()>::request_cancel(
+                    !matches!(coroutine.state(), CoroutineState::Syscall(_, _, _))
+                        && Self::is_cancelling(coroutine.name()),
+                );
+                CoroutineDump::resuming(self.name(), &coroutine);
+                let state = coroutine.resume();
+                Suspender::<(), ()>::request_cancel(false);
+                let state = state?;
+                match state {
+                    CoroutineState::Complete(_)
+                    | CoroutineState::Error(_)
+                    | CoroutineState::Cancelled => {
+                        _ = COROUTINE_OWNERS.remove(coroutine.name());
+                        CoroutineDump::remove(coroutine.name());
+                        if !UNPARKED.is_empty() {
+                            _ = UNPARKED.remove(&coroutine.id());
+                        }
+                        if let Some(peak) = coroutine.stack_peak() {
+                            self.stack_histogram.record(peak as u64);
+                        }
+                    }
+                    _ => CoroutineDump::resumed(self.name(), &coroutine),
+                }
+                match state {
                     CoroutineState::Syscall((), _, state) => {
                         //挂起协程到系统调用表
-                        let co_name = Box::leak(Box::from(coroutine.name()));
-                        //如果已包含，说明当前系统调用还有上层父系统调用，因此直接忽略插入结果
-                        _ = self.syscall.insert(co_name, coroutine);
-                        if let SyscallState::Suspend(timestamp) = state {
-                            self.syscall_suspend
-                                .push(SyscallSuspendItem { timestamp, co_name });
+                        let co_id = coroutine.id();
+                        let timer = if let SyscallState::Suspend(timestamp) = state {
+                            Some(self.syscall_suspend().insert(timestamp, co_id))
+                        } else {
+                            None
+                        };
+                        //如果已包含，说明当前系统调用还有上层父系统调用，只需移除旧的超时
+                        let cancelling = Self::is_cancelling(coroutine.name());
+                        if let Some((_, Some(timer))) =
+                            self.syscall.insert(co_id, (coroutine, timer))
+                        {
+                            _ = self.syscall_suspend().cancel(timer);
+                        }
+                        //取消请求可能在协程执行期间已被处理
+                        if cancelling {
+                            self.interrupt_syscall(co_id)?;
                         }
                     }
                     CoroutineState::Suspend((), timestamp) => {
-                        if timestamp > now() {
-                            //挂起协程到时间轮
-                            self.suspend.push(SuspendItem {
-                                timestamp,
-                                coroutine,
-                            });
-                        } else {
-                            //放入就绪队列尾部
-                            self.ready.push(coroutine);
-                        }
+                        self.suspend_co(coroutine, timestamp)?;
                     }
                     CoroutineState::Complete(result) => {
-                        let co_name = Box::leak(Box::from(coroutine.name()));
-                        assert!(
-                            self.results.insert(co_name, Ok(result)).is_none(),
-                            "not consume result"
-                        );
+                        Self::clean_cancel(coroutine.name());
+                        //raw coroutine没有类型化的结果
+                        let result = coroutine.take_result().unwrap_or_else(|| Box::new(result));
+                        self.complete(coroutine.id(), Ok(result));
                     }
-                    CoroutineState::Error(message) => {
-                        let co_name = Box::leak(Box::from(coroutine.name()));
-                        assert!(
-                            self.results.insert(co_name, Err(message)).is_none(),
-                            "not consume result"
+                    CoroutineState::Error(_) => {
+                        Self::clean_cancel(coroutine.name());
+                        //错误可能已被协程池转交给正在执行的任务
+                        if let Some(error) = coroutine.take_error() {
+                            self.complete(coroutine.id(), Err(error));
+                        }
+                    }
+                    CoroutineState::Cancelled => {
+                        Self::clean_cancel(coroutine.name());
+                        self.complete(
+                            coroutine.id(),
+                            Err(JoinError::new(JoinErrorKind::Cancelled)),
                         );
                     }
                     _ => {
@@ -318,54 +719,199 @@ impl<'s> Scheduler<'s> {
         }
     }
 
+    fn suspend_co(
+        &mut self,
+        coroutine: SchedulableCoroutine<'s>,
+        timestamp: u64,
+    ) -> std::io::Result<()> {
+        let co_id = coroutine.id();
+        //抢占产生的挂起时间戳不同，不能当作park
+        let parking = PARKING.get() == Some((co_id, timestamp));
+        if parking {
+            PARKING.set(None);
+        }
+        if Self::is_cancelling(coroutine.name()) {
+            //取消请求可能在协程执行期间已被处理，不再挂起，在挂起点展开
+            coroutine.wakeup()?;
+            self.ready.push(coroutine);
+        } else if parking {
+            //在park之前，可能已经被unpark
+            if UNPARKED.remove(&co_id).is_some() {
+                coroutine.wakeup()?;
+                self.ready.push(coroutine);
+            } else {
+                let timer = self.suspend.insert(timestamp, coroutine);
+                _ = self.suspended.insert(co_id, timer);
+                _ = self.parked.insert(co_id, timer);
+            }
+        } else if timestamp > now() {
+            //挂起协程到时间轮
+            let timer = self.suspend.insert(timestamp, coroutine);
+            _ = self.suspended.insert(co_id, timer);
+        } else {
+            //放入就绪队列尾部
+            self.ready.push(coroutine);
+        }
+        Ok(())
+    }
+
+    fn pop_ready(&mut self) -> Option<SchedulableCoroutine<'s>> {
+        if SchedulePolicy::Fifo == self.policy {
+            return self.ready.pop();
+        }
+        //只取本地队列中的协程排序，为空时才从其他队列steal
+        for _ in 0..self.ready.len() {
+            if let Some(coroutine) = self.ready.pop() {
+                self.push_fair(coroutine);
+            }
+        }
+        if self.fair.is_empty() {
+            if let Some(coroutine) = self.ready.pop() {
+                self.push_fair(coroutine);
+            }
+        }
+        let ((vruntime, _), coroutine) = self.fair.pop_first()?;
+        self.min_vruntime = self.min_vruntime.max(vruntime);
+        Some(coroutine)
+    }
+
+    fn push_fair(&mut self, coroutine: SchedulableCoroutine<'s>) {
+        let index = coroutine
+            .priority()
+            .unwrap_or(DEFAULT_PRECEDENCE)
+            .clamp(-20, 19)
+            + 20;
+        let weight = WEIGHTS[usize::try_from(index).expect("invalid priority")];
+        //刚创建或长时间挂起的协程，虚拟运行时间不能落后太多，否则会独占CPU
+        let floor = self
+            .min_vruntime
+            .saturating_sub(u64::try_from(SLICE.as_nanos()).expect("overflow"));
+        let vruntime = if let Some(runtime) = coroutine.get_mut::<Runtime>(RUNTIME) {
+            let delta = runtime.total - runtime.charged;
+            runtime.charged = runtime.total;
+            runtime.vruntime = runtime
+                .vruntime
+                .saturating_add(delta.saturating_mul(1024) / weight)
+                .max(floor);
+            runtime.vruntime
+        } else {
+            _ = coroutine.put(
+                RUNTIME,
+                Runtime {
+                    vruntime: self.min_vruntime,
+                    ..Runtime::default()
+                },
+            );
+            self.min_vruntime
+        };
+        self.fair_seq += 1;
+        _ = self.fair.insert((vruntime, self.fair_seq), coroutine);
+    }
+
+    fn syscall_suspend(&self) -> MutexGuard<'_, TimerWheel<CoroutineId>> {
+        self.syscall_suspend
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
     fn check_ready(&mut self) -> std::io::Result<()> {
-        // Check if the elements in the suspend queue are ready
-        while let Some(item) = self.suspend.peek() {
-            if now() < item.timestamp {
-                break;
+        if !CANCEL_REQUESTS.is_empty() {
+            if let Some((_, requests)) = CANCEL_REQUESTS.remove(&self.key) {
+                self.wakeup_cancelling(requests)?;
             }
-            if let Some(item) = self.suspend.pop() {
-                item.coroutine.ready()?;
-                self.ready.push(item.coroutine);
+        }
+        if !self.parked.is_empty() && !UNPARKED.is_empty() {
+            self.wakeup_unparked()?;
+        }
+        // Check if the elements in the suspend queue are ready
+        while let Some((_, coroutine)) = self.suspend.pop_expired(now()) {
+            _ = self.suspended.remove(&coroutine.id());
+            if !self.parked.is_empty() {
+                //park超时
+                _ = self.parked.remove(&coroutine.id());
             }
+            coroutine.ready()?;
+            CoroutineDump::record(self.name(), &coroutine);
+            self.ready.push_woken(coroutine);
         }
         // Check if the elements in the syscall suspend queue are ready
-        while let Some(item) = self.syscall_suspend.peek() {
-            if now() < item.timestamp {
+        loop {
+            let Some((_, co_id)) = self.syscall_suspend().pop_expired(now()) else {
                 break;
-            }
-            if let Some(item) = self.syscall_suspend.pop() {
-                if let Some((_, co)) = self.syscall.remove(item.co_name) {
-                    match co.state() {
-                        CoroutineState::Syscall(val, syscall, SyscallState::Suspend(_)) => {
-                            co.syscall(val, syscall, SyscallState::Timeout)?;
-                            self.ready.push(co);
-                        }
-                        _ => unreachable!("check_ready should never execute to here"),
+            };
+            if let Some((_, (co, _))) = self.syscall.remove(&co_id) {
+                match co.state() {
+                    CoroutineState::Syscall(val, syscall, SyscallState::Suspend(_)) => {
+                        co.syscall(val, syscall, SyscallState::Timeout)?;
+                        CoroutineDump::record(self.name(), &co);
+                        self.ready.push_woken(co);
                     }
+                    _ => unreachable!("check_ready should never execute to here"),
                 }
             }
         }
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::scheduler::SyscallSuspendItem;
-    use std::collections::BinaryHeap;
+    fn wakeup_unparked(&mut self) -> std::io::Result<()> {
+        let unparked: Vec<CoroutineId> = self
+            .parked
+            .keys()
+            .filter(|co_id| UNPARKED.remove(co_id).is_some())
+            .copied()
+            .collect();
+        for co_id in unparked {
+            let Some(timer) = self.parked.remove(&co_id) else {
+                continue;
+            };
+            _ = self.suspended.remove(&co_id);
+            if let Some(coroutine) = self.suspend.cancel(timer) {
+                coroutine.wakeup()?;
+                CoroutineDump::record(self.name(), &coroutine);
+                self.ready.push_woken(coroutine);
+            }
+        }
+        Ok(())
+    }
 
-    #[test]
-    fn test_small_heap() {
-        let mut heap = BinaryHeap::default();
-        for timestamp in (0..10).rev() {
-            heap.push(SyscallSuspendItem {
-                timestamp,
-                co_name: "test",
-            });
+    fn wakeup_cancelling(&mut self, requests: Vec<(CoroutineId, String)>) -> std::io::Result<()> {
+        for (co_id, co_name) in requests {
+            //协程可能已经执行完被取消的任务
+            if !Self::is_cancelling(&co_name) {
+                continue;
+            }
+            // Wake up the cancelled coroutine in the suspend queue
+            if let Some(timer) = self.suspended.remove(&co_id) {
+                _ = self.parked.remove(&co_id);
+                if let Some(coroutine) = self.suspend.cancel(timer) {
+                    coroutine.wakeup()?;
+                    CoroutineDump::record(self.name(), &coroutine);
+                    self.ready.push(coroutine);
+                }
+                continue;
+            }
+            // Interrupt the cancelled coroutine in the syscall suspend queue
+            self.interrupt_syscall(co_id)?;
         }
-        for timestamp in 0..10 {
-            assert_eq!(timestamp, heap.pop().unwrap().timestamp);
+        Ok(())
+    }
+
+    fn interrupt_syscall(&self, co_id: CoroutineId) -> std::io::Result<()> {
+        let Some((_, (co, Some(timer)))) = self
+            .syscall
+            .remove_if(&co_id, |_, (_, timer)| timer.is_some())
+        else {
+            return Ok(());
+        };
+        _ = self.syscall_suspend().cancel(timer);
+        match co.state() {
+            CoroutineState::Syscall(val, syscall, SyscallState::Suspend(_)) => {
+                co.syscall(val, syscall, SyscallState::Timeout)?;
+                CoroutineDump::record(self.name(), &co);
+                self.ready.push(co);
+            }
+            _ => unreachable!("interrupt_syscall should never execute to here"),
         }
+        Ok(())
     }
 }
@@ -74,3 +74,742 @@ fn co_pool_stop() -> std::io::Result<()> {
     )
     .map(|_| ())
 }
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_cancel() -> std::io::Result<()> {
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    let queued = pool.submit_task(
+        None,
+        |_| panic!("cancelled task should not run"),
+        None,
+        None,
+    )?;
+    pool.try_cancel_task(&queued);
+    let running = pool.submit_task(
+        None,
+        |_| {
+            if let Some(suspender) = open_coroutine_core::scheduler::SchedulableSuspender::current()
+            {
+                suspender.delay(std::time::Duration::from_secs(10));
+            }
+            Some(1)
+        },
+        None,
+        None,
+    )?;
+    _ = pool.try_timed_schedule_task(std::time::Duration::from_millis(100))?;
+    pool.try_cancel_task(&running);
+    _ = pool.try_timed_schedule_task(std::time::Duration::from_millis(100))?;
+    for task_name in [queued, running] {
+        let error = pool
+            .wait_task_result(&task_name, std::time::Duration::from_secs(1))?
+            .expect_err("task should be cancelled");
+        assert!(error.is_cancelled());
+    }
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_inherit_locals() -> std::io::Result<()> {
+    open_coroutine_core::coroutine_local! {
+        inheritable static REQUEST_ID: usize = 0;
+        static TENANT_ID: usize = 0;
+    }
+    let child = "test_inherit_locals_child";
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(2);
+    _ = pool.submit_task(
+        None,
+        move |_| {
+            REQUEST_ID.set(1);
+            TENANT_ID.set(1);
+            _ = open_coroutine_core::co_pool::CoroutinePool::current()
+                .expect("current pool not found")
+                .submit_task(
+                    Some(String::from(child)),
+                    |_| Some(REQUEST_ID.with(|r| *r) * 10 + TENANT_ID.with(|t| *t)),
+                    None,
+                    None,
+                )
+                .expect("submit child task failed");
+            None::<usize>
+        },
+        None,
+        None,
+    )?;
+    pool.try_schedule_task()?;
+    let result = pool
+        .wait_task_result(child, std::time::Duration::from_secs(1))?
+        .expect("child task failed");
+    assert_eq!(Some(&Some(10_usize)), result.downcast_ref());
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_typed_result() -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Debug)]
+    struct DropCounter(Arc<AtomicUsize>);
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            _ = self.0.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    let joined = pool.submit_task(None, |_| String::from("typed"), None, None)?;
+    let counter = dropped.clone();
+    _ = pool.submit_task(None, move |_| DropCounter(counter), None, None)?;
+    pool.try_schedule_task()?;
+    let result = pool
+        .wait_task_result(&joined, std::time::Duration::from_secs(1))?
+        .expect("task failed");
+    assert!(result.downcast_ref::<usize>().is_none());
+    assert_eq!(
+        "typed",
+        *result.downcast::<String>().expect("unexpected type")
+    );
+    // the result which nobody joins should be dropped with the pool
+    assert_eq!(0, dropped.load(Ordering::Acquire));
+    drop(pool);
+    assert_eq!(1, dropped.load(Ordering::Acquire));
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_join_error() -> std::io::Result<()> {
+    use open_coroutine_core::common::join_error::JoinErrorKind;
+
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    let panicked = pool.submit_task(
+        None,
+        |p| panic!("test panic with {p:?}, just ignore it"),
+        Some(1),
+        None,
+    )?;
+    let trapped = pool.submit_task(
+        None,
+        |_| {
+            unsafe { std::ptr::write_volatile(1 as *mut u8, 0) };
+            None::<usize>
+        },
+        None,
+        None,
+    )?;
+    let succeed = pool.submit_task(None, |_| 1_usize, None, None)?;
+    pool.try_schedule_task()?;
+    let error = pool
+        .wait_task_result(&panicked, std::time::Duration::from_secs(1))?
+        .expect_err("task should panic");
+    assert!(error.is_panic());
+    assert_eq!("test panic with Some(1), just ignore it", error.message());
+    let payload = error.into_panic();
+    assert_eq!(
+        Some(&String::from("test panic with Some(1), just ignore it")),
+        payload.downcast_ref()
+    );
+    // the worker coroutine is broken by the trap, and the task fails with it
+    let error = pool
+        .wait_task_result(&trapped, std::time::Duration::from_secs(1))?
+        .expect_err("task should trap");
+    assert_eq!(JoinErrorKind::InvalidMemory, error.kind());
+    // a new worker coroutine will be created for the remaining tasks
+    pool.try_schedule_task()?;
+    let result = pool
+        .wait_task_result(&succeed, std::time::Duration::from_secs(1))?
+        .expect("task failed");
+    assert_eq!(Some(&1), result.downcast_ref::<usize>());
+    let error = pool
+        .wait_task_result("not_exists", std::time::Duration::from_millis(10))?
+        .expect_err("task not exists");
+    assert!(error.is_timed_out());
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_earliest_deadline() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::TaskPolicy;
+    use open_coroutine_core::common::join_error::JoinErrorKind;
+    use open_coroutine_core::common::now;
+    use std::sync::{Arc, Mutex};
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    pool.set_task_policy(TaskPolicy::EarliestDeadline);
+    assert_eq!(TaskPolicy::EarliestDeadline, pool.task_policy());
+    let second = 1_000_000_000;
+    for (name, deadline) in [
+        ("late", Some(now() + 10 * second)),
+        ("early", Some(now() + second)),
+        ("missed", Some(now() - second)),
+        ("none", None),
+    ] {
+        let order = order.clone();
+        let task = move |_| order.lock().unwrap().push(name);
+        _ = match deadline {
+            Some(deadline) => {
+                pool.submit_deadline_task(Some(String::from(name)), task, None, None, deadline)?
+            }
+            None => pool.submit_task(Some(String::from(name)), task, None, None)?,
+        };
+    }
+    pool.try_schedule_task()?;
+    assert_eq!(vec!["early", "late", "none"], *order.lock().unwrap());
+    let error = pool
+        .wait_task_result("missed", std::time::Duration::from_secs(1))?
+        .expect_err("task should miss its deadline");
+    assert_eq!(JoinErrorKind::DeadlineMissed, error.kind());
+    for name in ["early", "late", "none"] {
+        assert!(pool
+            .wait_task_result(name, std::time::Duration::from_secs(1))?
+            .is_ok());
+    }
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_submit_tasks() -> std::io::Result<()> {
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    let names = pool.submit_tasks((0..1000usize).map(|i| (move |_| i * 2, None)), None)?;
+    assert_eq!(1000, names.len());
+    pool.try_schedule_task()?;
+    for (i, name) in names.iter().enumerate() {
+        let result = pool
+            .wait_task_result(name, std::time::Duration::from_secs(1))?
+            .expect("task failed");
+        assert_eq!(i * 2, *result.downcast::<usize>().expect("unexpected type"));
+    }
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_reject_policy() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::RejectPolicy;
+    use open_coroutine_core::common::join_error::{JoinError, JoinErrorKind};
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    pool.set_task_capacity(1);
+    assert_eq!(RejectPolicy::Abort, pool.reject_policy());
+    _ = pool.submit_task(Some(String::from("first")), |_| 1, None, None)?;
+    let error = pool
+        .submit_task(Some(String::from("aborted")), |_| 2, None, None)
+        .expect_err("task should be rejected");
+    let error = error
+        .into_inner()
+        .and_then(|e| e.downcast::<JoinError>().ok())
+        .expect("unexpected error");
+    assert!(error.is_rejected());
+    assert!(pool
+        .submit_tasks((3..5).map(|i| (move |_| i, None)), None)
+        .is_err());
+
+    pool.set_reject_policy(RejectPolicy::CallerRuns);
+    let thread = std::thread::current().id();
+    _ = pool.submit_task(
+        Some(String::from("caller")),
+        move |_| thread == std::thread::current().id(),
+        None,
+        None,
+    )?;
+    let result = pool.try_get_task_result("caller").expect("task not run");
+    assert!(*result
+        .expect("task failed")
+        .downcast::<bool>()
+        .expect("unexpected type"));
+
+    pool.set_reject_policy(RejectPolicy::DiscardOldest);
+    _ = pool.submit_task(Some(String::from("second")), |_| 5, None, None)?;
+    let error = pool
+        .try_get_task_result("first")
+        .expect("task not discarded")
+        .expect_err("task should be discarded");
+    assert_eq!(JoinErrorKind::Rejected, error.kind());
+    pool.try_schedule_task()?;
+    let result = pool
+        .wait_task_result("second", std::time::Duration::from_secs(1))?
+        .expect("task failed");
+    assert_eq!(5, *result.downcast::<i32>().expect("unexpected type"));
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_reject_block() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::{CoroutinePool, RejectPolicy};
+    let mut pool = CoroutinePool::default();
+    pool.set_max_size(2);
+    pool.set_task_capacity(1);
+    pool.set_reject_policy(RejectPolicy::Block);
+    _ = pool.submit_task(
+        Some(String::from("submitter")),
+        |_| {
+            let pool = CoroutinePool::current().expect("current pool not found");
+            for i in 0..3 {
+                // the submitter is parked until another worker takes the queued task
+                _ = pool.submit_task(Some(format!("blocked-{i}")), move |_| i, None, None)?;
+            }
+            Ok::<(), std::io::Error>(())
+        },
+        None,
+        None,
+    )?;
+    _ = pool.try_timed_schedule_task(std::time::Duration::from_secs(3))?;
+    assert!(pool
+        .wait_task_result("submitter", std::time::Duration::from_secs(1))?
+        .is_ok());
+    for i in 0..3 {
+        let result = pool
+            .wait_task_result(&format!("blocked-{i}"), std::time::Duration::from_secs(1))?
+            .expect("task failed");
+        assert_eq!(i, *result.downcast::<i32>().expect("unexpected type"));
+    }
+    // the blocked submitter is rejected when the pool stops
+    let mut pool = CoroutinePool::default();
+    pool.set_max_size(1);
+    pool.set_task_capacity(1);
+    pool.set_reject_policy(RejectPolicy::Block);
+    _ = pool.submit_task(
+        Some(String::from("stopped-submitter")),
+        |_| {
+            let pool = CoroutinePool::current().expect("current pool not found");
+            _ = pool.submit_task(None, |_| {}, None, None)?;
+            // no other worker takes the queued task
+            pool.submit_task(None, |_| {}, None, None)
+        },
+        None,
+        None,
+    )?;
+    _ = pool.try_timed_schedule_task(std::time::Duration::from_millis(50))?;
+    pool.stop(std::time::Duration::from_secs(1))?;
+    let result = pool
+        .try_get_task_result("stopped-submitter")
+        .expect("no result")
+        .expect("task failed");
+    assert!(result
+        .downcast::<std::io::Result<String>>()
+        .expect("unexpected type")
+        .is_err());
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_schedule() -> std::io::Result<()> {
+    use open_coroutine_core::common::join_error::JoinErrorKind;
+    use open_coroutine_core::common::now;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    let start = now();
+    _ = pool.schedule_at(
+        Some(String::from("delayed")),
+        |_| now(),
+        None,
+        None,
+        start + 50_000_000,
+    )?;
+    _ = pool.schedule_at(Some(String::from("never")), |_| 0, None, None, u64::MAX)?;
+    let rate = Arc::new(AtomicUsize::new(0));
+    let counter = rate.clone();
+    _ = pool.schedule_with_fixed_rate(
+        Some(String::from("rate")),
+        move |_| _ = counter.fetch_add(1, Ordering::Release),
+        None,
+        None,
+        Duration::ZERO,
+        Duration::from_millis(10),
+    )?;
+    let delay = Arc::new(AtomicUsize::new(0));
+    let counter = delay.clone();
+    _ = pool.schedule_with_fixed_delay(
+        Some(String::from("delay")),
+        move |_| _ = counter.fetch_add(1, Ordering::Release),
+        None,
+        None,
+        Duration::from_millis(5),
+        Duration::from_millis(10),
+    )?;
+    assert!(pool
+        .schedule_with_fixed_rate(None, |_| {}, None, None, Duration::ZERO, Duration::ZERO)
+        .is_err());
+    pool.try_cancel_task("never");
+    while now() < start + 100_000_000 {
+        _ = pool.try_timed_schedule_task(Duration::from_millis(1))?;
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(rate.load(Ordering::Acquire) >= 3);
+    assert!(delay.load(Ordering::Acquire) >= 3);
+    let fired = pool
+        .try_get_task_result("delayed")
+        .expect("task not run")
+        .expect("task failed");
+    assert!(*fired.downcast::<u64>().expect("unexpected type") >= start + 50_000_000);
+    let error = pool
+        .try_get_task_result("never")
+        .expect("task not cancelled")
+        .expect_err("task should be cancelled");
+    assert_eq!(JoinErrorKind::Cancelled, error.kind());
+    // no more runs after cancelled
+    pool.try_cancel_task("rate");
+    let runs = rate.load(Ordering::Acquire);
+    for _ in 0..30 {
+        _ = pool.try_timed_schedule_task(Duration::from_millis(1))?;
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(runs, rate.load(Ordering::Acquire));
+    let error = pool
+        .wait_task_result("rate", Duration::from_secs(1))?
+        .expect_err("task should be cancelled");
+    assert_eq!(JoinErrorKind::Cancelled, error.kind());
+    // cancelled while running without suspending
+    let busy = Arc::new(AtomicUsize::new(0));
+    let counter = busy.clone();
+    _ = pool.schedule_with_fixed_rate(
+        Some(String::from("busy")),
+        move |_| {
+            if 1 == counter.fetch_add(1, Ordering::Release) {
+                open_coroutine_core::co_pool::CoroutinePool::current()
+                    .expect("no pool")
+                    .try_cancel_task("busy");
+            }
+        },
+        None,
+        None,
+        Duration::ZERO,
+        Duration::from_millis(1),
+    )?;
+    for _ in 0..30 {
+        _ = pool.try_timed_schedule_task(Duration::from_millis(1))?;
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(2, busy.load(Ordering::Acquire));
+    let error = pool
+        .wait_task_result("busy", Duration::from_secs(1))?
+        .expect_err("task should be cancelled");
+    assert_eq!(JoinErrorKind::Cancelled, error.kind());
+    // the pending runs are cancelled when the pool stops
+    pool.stop(Duration::from_secs(1))?;
+    let error = pool
+        .wait_task_result("delay", Duration::from_secs(1))?
+        .expect_err("task should be cancelled");
+    assert_eq!(JoinErrorKind::Cancelled, error.kind());
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_continuation() -> std::io::Result<()> {
+    use open_coroutine_core::common::join_error::JoinErrorKind;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    let completed = Arc::new(Mutex::new(Vec::new()));
+    let first = pool.submit_task(None, |_| 1, None, None)?;
+    let second = pool.then(
+        &first,
+        |result| *result.expect("task failed").downcast::<i32>().unwrap() + 1,
+        None,
+    )?;
+    let third = pool.then(
+        &second,
+        |result| *result.expect("task failed").downcast::<i32>().unwrap() * 10,
+        None,
+    )?;
+    let recorder = completed.clone();
+    pool.on_complete(&third, move |result| {
+        recorder
+            .lock()
+            .unwrap()
+            .push(*result.expect("task failed").downcast::<i32>().unwrap());
+    })?;
+    let failed = pool.submit_task(None, |_| panic!("test panic, just ignore it"), None, None)?;
+    let recorder = completed.clone();
+    let next = pool.then(
+        &failed,
+        move |result| {
+            let error = result.expect_err("task should panic");
+            assert_eq!(JoinErrorKind::Panic, error.kind());
+            recorder.lock().unwrap().push(-1);
+        },
+        None,
+    )?;
+    // a task has at most one callback, the chained one is kept
+    let error = pool
+        .detach_task(&second)
+        .expect_err("the task already has a callback");
+    assert_eq!(std::io::ErrorKind::AlreadyExists, error.kind());
+    assert!(pool
+        .on_complete(&third, |_| unreachable!("the callback is rejected"))
+        .is_err());
+    pool.try_schedule_task()?;
+    assert_eq!(vec![-1, 20], *completed.lock().unwrap());
+    assert!(pool.try_get_task_result(&third).is_none());
+    assert!(pool
+        .wait_task_result(&next, Duration::from_secs(1))?
+        .is_ok());
+    // the callback runs now if the task has already completed
+    let done = pool.submit_task(None, |_| 3, None, None)?;
+    pool.try_schedule_task()?;
+    let recorder = completed.clone();
+    pool.on_complete(&done, move |result| {
+        recorder
+            .lock()
+            .unwrap()
+            .push(*result.expect("task failed").downcast::<i32>().unwrap());
+    })?;
+    assert_eq!(vec![-1, 20, 3], *completed.lock().unwrap());
+    // the unknown or joined tasks never complete
+    for task_name in ["unknown", done.as_str()] {
+        let error = pool
+            .on_complete(task_name, |_| unreachable!("the task never completes"))
+            .expect_err("the task should not be found");
+        assert_eq!(std::io::ErrorKind::NotFound, error.kind());
+        assert!(pool.then(task_name, |_| {}, None).is_err());
+        assert!(pool.detach_task(task_name).is_err());
+    }
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_detach_and_result_ttl() -> std::io::Result<()> {
+    use open_coroutine_core::common::join_error::{JoinError, JoinErrorKind};
+    use std::io::ErrorKind;
+    use std::sync::Arc;
+    use std::time::Duration;
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    // the result of a detached task is dropped on completion
+    let value = Arc::new(());
+    let result = value.clone();
+    let detached = pool.submit_task(None, move |_| result, None, None)?;
+    pool.detach_task(&detached)?;
+    pool.try_schedule_task()?;
+    assert_eq!(1, Arc::strong_count(&value));
+    assert!(pool.try_get_task_result(&detached).is_none());
+    // a name in use can't be reused until the result is retrieved
+    let name = String::from("duplicate");
+    _ = pool.submit_task(Some(name.clone()), |_| 1, None, None)?;
+    let error = pool
+        .submit_task(Some(name.clone()), |_| 2, None, None)
+        .expect_err("the name is in use");
+    assert_eq!(ErrorKind::AlreadyExists, error.kind());
+    pool.try_schedule_task()?;
+    assert!(pool
+        .submit_task(Some(name.clone()), |_| 2, None, None)
+        .is_err());
+    assert!(pool.try_get_task_result(&name).is_some());
+    _ = pool.submit_task(Some(name.clone()), |_| 2, None, None)?;
+    pool.try_schedule_task()?;
+    // the unclaimed results expire after the ttl
+    pool.set_result_ttl(Duration::from_millis(10).as_nanos().try_into().unwrap());
+    std::thread::sleep(Duration::from_millis(20));
+    let fresh = pool.submit_task(None, |_| 3, None, None)?;
+    pool.try_schedule_task()?;
+    assert_eq!(None, pool.task_status(&name));
+    let error = pool
+        .wait_task_result(&name, Duration::from_secs(1))?
+        .expect_err("the result should expire");
+    assert_eq!(JoinErrorKind::Expired, error.kind());
+    assert!(pool.try_get_task_result(&fresh).is_some());
+    // the name of an expired result can be reused
+    let reused = pool.submit_task(Some(String::from("reused")), |_| 4, None, None)?;
+    pool.try_schedule_task()?;
+    std::thread::sleep(Duration::from_millis(20));
+    _ = pool.submit_task(None, |_| 5, None, None)?;
+    pool.try_schedule_task()?;
+    assert_eq!(None, pool.task_status(&reused));
+    _ = pool.submit_task(Some(reused), |_| 6, None, None)?;
+    // the rejected task releases its name
+    pool.set_task_capacity(0);
+    let error = pool
+        .submit_task(Some(String::from("rejected")), |_| 7, None, None)
+        .expect_err("the task should be rejected");
+    assert_eq!(ErrorKind::Other, error.kind());
+    assert!(error
+        .into_inner()
+        .and_then(|e| e.downcast::<JoinError>().ok())
+        .is_some_and(|e| e.is_rejected()));
+    assert_eq!(None, pool.task_status("rejected"));
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_task_status() -> std::io::Result<()> {
+    use open_coroutine_core::co_pool::CoroutinePool;
+    use open_coroutine_core::common::constants::TaskStatus;
+    use std::sync::{Arc, Mutex};
+    let mut pool = CoroutinePool::default();
+    pool.set_max_size(1);
+    let observed = Arc::new(Mutex::new(None));
+    let recorder = observed.clone();
+    let running = pool.submit_task(
+        Some(String::from("status-running")),
+        move |_| {
+            *recorder.lock().unwrap() =
+                CoroutinePool::current().and_then(|pool| pool.task_status("status-running"));
+        },
+        None,
+        Some(2),
+    )?;
+    let failed = pool.submit_task(
+        Some(String::from("status-failed")),
+        |_| panic!("test panic, just ignore it"),
+        None,
+        Some(1),
+    )?;
+    assert_eq!(Some(TaskStatus::Queued), pool.task_status(&running));
+    assert_eq!(
+        vec![(failed.clone(), 1), (running.clone(), 2)],
+        pool.pending_tasks()
+    );
+    pool.try_schedule_task()?;
+    assert_eq!(Some(TaskStatus::Running), *observed.lock().unwrap());
+    assert!(pool.pending_tasks().is_empty());
+    assert_eq!(Some(TaskStatus::Completed), pool.task_status(&running));
+    assert_eq!(Some(TaskStatus::Failed), pool.task_status(&failed));
+    assert!(pool.try_get_task_result(&running).is_some());
+    assert_eq!(None, pool.task_status(&running));
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_shutdown_now() -> std::io::Result<()> {
+    use open_coroutine_core::common::constants::PoolState;
+    use open_coroutine_core::common::constants::TaskStatus;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::default();
+    pool.set_max_size(1);
+    let running = pool.submit_task(
+        None,
+        |_| {
+            if let Some(suspender) = open_coroutine_core::scheduler::SchedulableSuspender::current()
+            {
+                suspender.delay(Duration::from_secs(10));
+            }
+            Some(1)
+        },
+        None,
+        None,
+    )?;
+    _ = pool.try_timed_schedule_task(Duration::from_millis(100))?;
+    let queued = pool.submit_task(None, |_| Some(2), None, None)?;
+    let delayed = pool.schedule_at(
+        None,
+        |_| Some(3),
+        None,
+        None,
+        open_coroutine_core::common::get_timeout_time(Duration::from_secs(10)),
+    )?;
+    let count = Arc::new(AtomicUsize::new(0));
+    let count_in_task = count.clone();
+    let periodic = pool.schedule_with_fixed_delay(
+        None,
+        move |_| {
+            _ = count_in_task.fetch_add(1, Ordering::Release);
+        },
+        None,
+        None,
+        Duration::from_millis(10),
+        Duration::from_millis(10),
+    )?;
+    let mut tasks = pool.shutdown_now()?;
+    assert_eq!(PoolState::Stopping, pool.state());
+    assert!(pool.submit_task(None, |_| Some(4), None, None).is_err());
+    let mut names: Vec<String> = tasks
+        .iter()
+        .map(|task| String::from(task.get_name()))
+        .collect();
+    names.sort();
+    let mut expected = vec![queued.clone(), delayed.clone(), periodic.clone()];
+    expected.sort();
+    assert_eq!(expected, names);
+    // the returned tasks can still run elsewhere
+    let index = tasks
+        .iter()
+        .position(|task| task.get_name() == queued)
+        .unwrap();
+    let (_, result) = tasks.remove(index).run();
+    assert_eq!(Some(2), *result.unwrap().downcast::<Option<i32>>().unwrap());
+    // or be resubmitted to another pool with their due time and period
+    let mut other = open_coroutine_core::co_pool::CoroutinePool::default();
+    for task in tasks {
+        let name = String::from(task.get_name());
+        assert_eq!(name, other.resubmit_task(task)?);
+    }
+    assert_eq!(Some(TaskStatus::Queued), other.task_status(&delayed));
+    let start = open_coroutine_core::common::now();
+    while open_coroutine_core::common::now() < start + 100_000_000 {
+        _ = other.try_timed_schedule_task(Duration::from_millis(1))?;
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(Some(TaskStatus::Queued), other.task_status(&delayed));
+    assert!(count.load(Ordering::Acquire) > 1);
+    other.try_cancel_task(&delayed);
+    other.try_cancel_task(&periodic);
+    other.stop(Duration::from_secs(1))?;
+    pool.stop(Duration::from_secs(1))?;
+    assert_eq!(PoolState::Stopped, pool.state());
+    for task_name in [running, queued] {
+        let error = pool
+            .try_get_task_result(&task_name)
+            .expect("no result")
+            .expect_err("task should be cancelled");
+        assert!(error.is_cancelled());
+    }
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "preemptive")))]
+#[test]
+fn co_pool_strict_fifo() -> std::io::Result<()> {
+    use open_coroutine_core::scheduler::ReadyQueueKind;
+    use std::sync::{Arc, Mutex};
+
+    let mut pool = open_coroutine_core::co_pool::CoroutinePool::with_ready_queue(
+        String::from("strict-fifo"),
+        open_coroutine_core::common::constants::DEFAULT_STACK_SIZE,
+        0,
+        1,
+        0,
+        ReadyQueueKind::StrictFifo,
+    );
+    let events = Arc::new(Mutex::new(Vec::new()));
+    for i in 0..3 {
+        let events = events.clone();
+        _ = pool.submit_task(
+            None,
+            move |_| {
+                events.lock().unwrap().push(i);
+                Some(i)
+            },
+            None,
+            None,
+        )?;
+    }
+    pool.try_schedule_task()?;
+    assert_eq!(vec![0, 1, 2], *events.lock().unwrap());
+    Ok(())
+}
@@ -176,6 +176,11 @@ fn coroutine_trap() -> std::io::Result<()> {
         _ => false,
     };
     assert!(error);
+    let error = coroutine.take_error().expect("error not found");
+    assert_eq!(
+        open_coroutine_core::common::join_error::JoinErrorKind::InvalidMemory,
+        error.kind()
+    );
     Ok(())
 }
 
@@ -285,3 +290,178 @@ fn coroutine_syscall_not_preemptive() -> std::io::Result<()> {
         ))
     }
 }
+
+#[test]
+fn coroutine_local() -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct Value(usize);
+
+    impl Drop for Value {
+        fn drop(&mut self) {
+            _ = DROPPED.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    open_coroutine_core::coroutine_local! {
+        static VALUE: Value = Value(0);
+    }
+
+    VALUE.set(Value(1));
+    let mut coroutine = co!(|suspender, ()| {
+        VALUE.with(|v| assert_eq!(0, v.0));
+        VALUE.set(Value(2));
+        suspender.suspend();
+        VALUE.with(|v| assert_eq!(2, v.0));
+    })?;
+    assert_eq!(CoroutineState::Suspend((), 0), coroutine.resume()?);
+    // the value in thread is not changed
+    VALUE.with(|v| assert_eq!(1, v.0));
+    let dropped = DROPPED.load(Ordering::Acquire);
+    assert_eq!(CoroutineState::Complete(()), coroutine.resume()?);
+    assert_eq!(dropped + 1, DROPPED.load(Ordering::Acquire));
+    Ok(())
+}
+
+#[test]
+fn generator_basic() -> std::io::Result<()> {
+    use open_coroutine_core::coroutine::generator::Generator;
+
+    let mut generator = Generator::new(
+        None,
+        |yielder| {
+            for i in 0..3 {
+                yielder.suspend_with(format!("value{i}"));
+            }
+            vec![3]
+        },
+        None,
+    )?;
+    assert_eq!(Some(String::from("value0")), generator.next());
+    assert!(!generator.is_finished());
+    assert_eq!(
+        vec![String::from("value1"), String::from("value2")],
+        generator.by_ref().collect::<Vec<_>>()
+    );
+    assert!(generator.is_finished());
+    assert_eq!(None, generator.next());
+    assert_eq!(vec![3], generator.into_return().expect("not finished")?);
+    Ok(())
+}
+
+#[test]
+fn generator_drop_unfinished() -> std::io::Result<()> {
+    use open_coroutine_core::coroutine::generator::Generator;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    struct Sentinel(Rc<Cell<bool>>);
+
+    impl Drop for Sentinel {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    let dropped = Rc::new(Cell::new(false));
+    let sentinel = Sentinel(dropped.clone());
+    let generator = Generator::<usize, ()>::new(
+        None,
+        move |yielder| {
+            let _sentinel = sentinel;
+            for i in 0.. {
+                yielder.suspend_with(i);
+            }
+        },
+        None,
+    )?;
+    assert_eq!(vec![0, 1], generator.take(2).collect::<Vec<_>>());
+    assert!(dropped.get());
+    Ok(())
+}
+
+#[test]
+fn generator_panic() -> std::io::Result<()> {
+    use open_coroutine_core::coroutine::generator::Generator;
+
+    let mut generator = Generator::<usize, ()>::new(
+        None,
+        |yielder| {
+            yielder.suspend_with(1);
+            panic!("test panic, just ignore it");
+        },
+        None,
+    )?;
+    assert!(!generator.is_finished());
+    assert_eq!(vec![1], generator.by_ref().collect::<Vec<_>>());
+    let error = generator
+        .into_return()
+        .expect("not finished")
+        .expect_err("generator should panic");
+    assert!(error.is_panic());
+    assert_eq!("test panic, just ignore it", error.message());
+    Ok(())
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86", feature = "preemptive")))]
+#[test]
+fn generator_trap() -> std::io::Result<()> {
+    use open_coroutine_core::common::join_error::JoinErrorKind;
+    use open_coroutine_core::coroutine::generator::Generator;
+
+    let mut generator = Generator::<usize, ()>::new(
+        None,
+        |yielder| {
+            yielder.suspend_with(1);
+            unsafe { std::ptr::write_volatile(1 as *mut u8, 0) };
+        },
+        None,
+    )?;
+    assert_eq!(Some(1), generator.next());
+    assert_eq!(None, generator.next());
+    let error = generator
+        .into_return()
+        .expect("not finished")
+        .expect_err("generator should trap");
+    assert_eq!(JoinErrorKind::InvalidMemory, error.kind());
+    Ok(())
+}
+
+#[test]
+fn coroutine_lifecycle_listener() -> std::io::Result<()> {
+    use open_coroutine_core::coroutine::listener::Listener;
+    use open_coroutine_core::coroutine::local::CoroutineLocal;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Debug, Default)]
+    struct LifecycleListener(Arc<AtomicUsize>, Arc<AtomicUsize>);
+    impl Listener<(), ()> for LifecycleListener {
+        fn on_create(&self, _: &CoroutineLocal, _: &str, _: usize) {
+            _ = self.0.fetch_add(1, Ordering::Release);
+        }
+
+        fn on_drop(&self, _: &CoroutineLocal, _: &str) {
+            _ = self.1.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    let created = Arc::new(AtomicUsize::new(0));
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let mut coroutine = co!(|suspender: &Suspender<'_, (), ()>, ()| {
+        suspender.suspend();
+    })?;
+    coroutine.add_listener(LifecycleListener(created.clone(), dropped.clone()));
+    assert_eq!(1, created.load(Ordering::Acquire));
+    assert_eq!(CoroutineState::Suspend((), 0), coroutine.resume()?);
+    // the listener added to a started coroutine will not be notified with on_create
+    coroutine.add_listener(LifecycleListener(created.clone(), dropped.clone()));
+    assert_eq!(1, created.load(Ordering::Acquire));
+    // drop the unfinished coroutine
+    drop(coroutine);
+    assert_eq!(2, dropped.load(Ordering::Acquire));
+    Ok(())
+}
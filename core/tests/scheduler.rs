@@ -1,3 +1,4 @@
+use open_coroutine_core::common::constants::CoroutineState;
 use open_coroutine_core::scheduler::Scheduler;
 use std::time::Duration;
 
@@ -7,7 +8,7 @@ fn scheduler_basic() -> std::io::Result<()> {
     _ = scheduler.submit_co(
         |_, _| {
             println!("1");
-            None
+            None::<usize>
         },
         None,
         None,
@@ -15,7 +16,7 @@ fn scheduler_basic() -> std::io::Result<()> {
     _ = scheduler.submit_co(
         |_, _| {
             println!("2");
-            None
+            None::<usize>
         },
         None,
         None,
@@ -27,11 +28,11 @@ fn scheduler_basic() -> std::io::Result<()> {
 #[test]
 fn scheduler_backtrace() -> std::io::Result<()> {
     let mut scheduler = Scheduler::default();
-    _ = scheduler.submit_co(|_, _| None, None, None)?;
+    _ = scheduler.submit_co(|_, _| None::<usize>, None, None)?;
     _ = scheduler.submit_co(
         |_, _| {
             println!("{:?}", backtrace::Backtrace::new());
-            None
+            None::<usize>
         },
         None,
         None,
@@ -47,7 +48,7 @@ fn scheduler_suspend() -> std::io::Result<()> {
             println!("[coroutine1] suspend");
             suspender.suspend();
             println!("[coroutine1] back");
-            None
+            None::<usize>
         },
         None,
         None,
@@ -57,7 +58,7 @@ fn scheduler_suspend() -> std::io::Result<()> {
             println!("[coroutine2] suspend");
             suspender.suspend();
             println!("[coroutine2] back");
-            None
+            None::<usize>
         },
         None,
         None,
@@ -73,7 +74,7 @@ fn scheduler_delay() -> std::io::Result<()> {
             println!("[coroutine] delay");
             suspender.delay(Duration::from_millis(100));
             println!("[coroutine] back");
-            None
+            None::<usize>
         },
         None,
         None,
@@ -117,10 +118,344 @@ fn scheduler_listener() -> std::io::Result<()> {
     scheduler.submit_co(
         |_, _| {
             println!("2");
-            None
+            None::<usize>
         },
         None,
         None,
     )?;
     scheduler.try_schedule()
 }
+
+#[test]
+fn scheduler_dump() -> std::io::Result<()> {
+    use open_coroutine_core::dump::{
+        is_capture_backtrace, is_dump_enabled, set_capture_backtrace, set_dump_enabled,
+    };
+
+    /// Restore the global dump settings after the test.
+    struct DumpSettings(bool, bool);
+    impl Drop for DumpSettings {
+        fn drop(&mut self) {
+            set_capture_backtrace(self.1);
+            set_dump_enabled(self.0);
+        }
+    }
+
+    let _settings = DumpSettings(is_dump_enabled(), is_capture_backtrace());
+    set_dump_enabled(true);
+    set_capture_backtrace(true);
+    let mut scheduler = Scheduler::default();
+    _ = scheduler.submit_co(
+        |suspender, _| {
+            suspender.delay(Duration::from_millis(200));
+            None::<usize>
+        },
+        None,
+        Some(1),
+    )?;
+    scheduler.try_schedule()?;
+    let dump = scheduler.dump();
+    assert_eq!(1, dump.coroutines.len());
+    let co = &dump.coroutines[0];
+    assert!(matches!(co.state, CoroutineState::Suspend((), _)));
+    assert!(co.syscall.is_none());
+    assert!(co.deadline.is_some());
+    assert_eq!(Some(1), co.priority);
+    let stack_used = co.stack_used.expect("stack usage not recorded");
+    assert!(0 < stack_used && stack_used <= co.stack_size);
+    assert!(co.backtrace.is_some());
+    std::thread::sleep(Duration::from_millis(200));
+    scheduler.try_schedule()?;
+    assert!(scheduler.dump().coroutines.is_empty());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn scheduler_stack_peak() -> std::io::Result<()> {
+    use open_coroutine_core::coroutine::listener::Listener;
+    use open_coroutine_core::coroutine::local::CoroutineLocal;
+    use open_coroutine_core::coroutine::{is_stack_canary, set_stack_canary};
+    use open_coroutine_core::scheduler::SchedulableCoroutine;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Debug, Default)]
+    struct StackListener(Arc<AtomicUsize>);
+    impl Listener<(), Option<usize>> for StackListener {
+        fn on_stack_usage(&self, _: &CoroutineLocal, stack_size: usize, peak: usize) {
+            assert!(stack_size > 0);
+            self.0.store(peak, Ordering::Release);
+        }
+    }
+
+    fn use_stack(size: usize) -> u8 {
+        let buf = vec![1u8; size];
+        let mut array = [0u8; 16 * 1024];
+        array.copy_from_slice(&buf[..16 * 1024]);
+        std::hint::black_box(&mut array)[size - 1]
+    }
+
+    /// Restore the global stack canary setting after the test.
+    struct StackCanary(bool);
+    impl Drop for StackCanary {
+        fn drop(&mut self) {
+            set_stack_canary(self.0);
+        }
+    }
+
+    let _canary = StackCanary(is_stack_canary());
+    set_stack_canary(true);
+    let peak = Arc::new(AtomicUsize::new(0));
+    let mut scheduler = Scheduler::default();
+    scheduler.add_listener(StackListener(peak.clone()));
+    _ = scheduler.submit_co(
+        |_, _| {
+            _ = use_stack(16 * 1024);
+            None::<usize>
+        },
+        Some(64 * 1024),
+        None,
+    )?;
+    scheduler.try_schedule()?;
+    let main_peak = peak.load(Ordering::Acquire);
+    assert!(16 * 1024 < main_peak && main_peak < 64 * 1024);
+    assert_eq!(1, scheduler.stack_histogram().count());
+    assert_eq!(main_peak as u64, scheduler.stack_histogram().max());
+
+    // the stacks allocated by maybe_grow should be counted
+    _ = scheduler.submit_co(
+        |_, _| {
+            SchedulableCoroutine::maybe_grow_with(usize::MAX, 64 * 1024, || use_stack(16 * 1024))
+                .expect("grow failed");
+            None::<usize>
+        },
+        Some(64 * 1024),
+        None,
+    )?;
+    scheduler.try_schedule()?;
+    assert!(peak.load(Ordering::Acquire) > 64 * 1024 + 16 * 1024);
+    assert_eq!(2, scheduler.stack_histogram().count());
+    Ok(())
+}
+
+#[test]
+fn scheduler_typed_result() -> std::io::Result<()> {
+    let mut scheduler = Scheduler::default();
+    let typed = scheduler.submit_co(|_, _| String::from("typed"), None, None)?;
+    let raw = scheduler.submit_co(|_, _| panic!("test panic, just ignore it"), None, None)?;
+    scheduler.try_schedule()?;
+    let result = scheduler.try_get_co_result(typed).expect("no result");
+    assert_eq!(
+        "typed",
+        *result
+            .expect("coroutine failed")
+            .downcast::<String>()
+            .expect("unexpected type")
+    );
+    let error = scheduler
+        .try_get_co_result(raw)
+        .expect("no result")
+        .expect_err("coroutine should panic");
+    assert_eq!("test panic, just ignore it", error.message());
+    assert_eq!(
+        Some(&"test panic, just ignore it"),
+        error.into_panic().downcast_ref::<&str>()
+    );
+    assert!(scheduler.try_get_co_result(typed).is_none());
+    Ok(())
+}
+
+#[test]
+fn scheduler_lifecycle_listener() -> std::io::Result<()> {
+    use open_coroutine_core::coroutine::listener::Listener;
+    use open_coroutine_core::coroutine::local::CoroutineLocal;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Default)]
+    struct LifecycleListener(Arc<Mutex<Vec<String>>>);
+    impl Listener<(), Option<usize>> for LifecycleListener {
+        fn on_create(&self, _: &CoroutineLocal, name: &str, stack_size: usize) {
+            assert!(stack_size > 0);
+            self.0.lock().unwrap().push(format!("create {name}"));
+        }
+
+        fn on_drop(&self, _: &CoroutineLocal, name: &str) {
+            self.0.lock().unwrap().push(format!("drop {name}"));
+        }
+    }
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut scheduler = Scheduler::default();
+    scheduler.add_listener(LifecycleListener(events.clone()));
+    _ = scheduler.submit_co(|_, _| None::<usize>, None, None)?;
+    assert_eq!(1, events.lock().unwrap().len());
+    scheduler.try_schedule()?;
+    let events = events.lock().unwrap();
+    assert_eq!(2, events.len());
+    let co_name = events[0].strip_prefix("create ").expect("not created");
+    assert_eq!(format!("drop {co_name}"), events[1]);
+    Ok(())
+}
+
+#[test]
+fn scheduler_fair() -> std::io::Result<()> {
+    use open_coroutine_core::scheduler::SchedulePolicy;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut scheduler = Scheduler::default();
+    scheduler.set_policy(SchedulePolicy::Fair);
+    assert_eq!(SchedulePolicy::Fair, scheduler.policy());
+    let heavy = events.clone();
+    _ = scheduler.submit_co(
+        move |suspender, ()| {
+            for _ in 0..3 {
+                heavy.lock().unwrap().push("heavy");
+                let start = Instant::now();
+                while start.elapsed() < Duration::from_millis(5) {}
+                suspender.suspend();
+            }
+        },
+        None,
+        None,
+    )?;
+    let light = events.clone();
+    _ = scheduler.submit_co(
+        move |suspender, ()| {
+            for _ in 0..3 {
+                light.lock().unwrap().push("light");
+                suspender.suspend();
+            }
+        },
+        None,
+        None,
+    )?;
+    scheduler.try_schedule()?;
+    assert_eq!(
+        vec!["heavy", "light", "light", "light", "heavy", "heavy"],
+        *events.lock().unwrap()
+    );
+    Ok(())
+}
+
+#[test]
+fn scheduler_park() -> std::io::Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let unparker = Arc::new(Mutex::new(None));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut scheduler = Scheduler::default();
+    let (slot, parked) = (unparker.clone(), events.clone());
+    _ = scheduler.submit_co(
+        move |_, ()| {
+            // the permit is consumed by the next park
+            Scheduler::unparker().unpark();
+            Scheduler::park();
+            *slot.lock().unwrap() = Some(Scheduler::unparker());
+            parked.lock().unwrap().push("park");
+            Scheduler::park();
+            parked.lock().unwrap().push("unparked");
+            Scheduler::park_timeout(Duration::from_millis(10));
+            parked.lock().unwrap().push("timeout");
+        },
+        None,
+        None,
+    )?;
+    scheduler.try_schedule()?;
+    assert_eq!(vec!["park"], *events.lock().unwrap());
+    let unparker = unparker.lock().unwrap().take().expect("not parked");
+    std::thread::spawn(move || unparker.unpark())
+        .join()
+        .expect("unpark failed");
+    scheduler.try_schedule()?;
+    assert_eq!(vec!["park", "unparked"], *events.lock().unwrap());
+    std::thread::sleep(Duration::from_millis(20));
+    scheduler.try_schedule()?;
+    assert_eq!(vec!["park", "unparked", "timeout"], *events.lock().unwrap());
+    Ok(())
+}
+
+#[test]
+fn scheduler_cancel() -> std::io::Result<()> {
+    use open_coroutine_core::common::join_error::JoinErrorKind;
+    use open_coroutine_core::scheduler::SchedulableCoroutine;
+    use std::sync::{Arc, Mutex};
+
+    // the schedulers created on the same thread have the same default name
+    let mut scheduler = Scheduler::default();
+    let mut other = Scheduler::default();
+    let name = Arc::new(Mutex::new(String::new()));
+    let slot = name.clone();
+    let co_id = scheduler.submit_co(
+        move |suspender, ()| {
+            *slot.lock().unwrap() = String::from(SchedulableCoroutine::current().unwrap().name());
+            suspender.delay(Duration::from_secs(10));
+        },
+        None,
+        None,
+    )?;
+    _ = scheduler.try_timed_schedule(Duration::from_millis(10))?;
+    Scheduler::try_cancel_coroutine(&name.lock().unwrap());
+    // the request is only handled by the owner
+    _ = other.try_timed_schedule(Duration::from_millis(10))?;
+    _ = scheduler.try_timed_schedule(Duration::from_millis(10))?;
+    let error = scheduler
+        .try_get_co_result(co_id)
+        .expect("coroutine not cancelled")
+        .expect_err("coroutine should be cancelled");
+    assert_eq!(JoinErrorKind::Cancelled, error.kind());
+    Ok(())
+}
+
+#[test]
+fn scheduler_lifo_slot() -> std::io::Result<()> {
+    use open_coroutine_core::common::ready_queue::{FifoQueue, LifoSlotQueue};
+    use std::sync::{Arc, Mutex};
+
+    for (lifo, expected) in [
+        (false, ["park", "unpark", "3", "unparked"]),
+        (true, ["park", "unpark", "unparked", "3"]),
+    ] {
+        let ready: Box<dyn open_coroutine_core::common::ready_queue::ReadyQueue<_>> = if lifo {
+            Box::new(LifoSlotQueue::new(FifoQueue::default()))
+        } else {
+            Box::new(FifoQueue::default())
+        };
+        let mut scheduler = Scheduler::with_ready_queue(String::from("lifo"), 128 * 1024, ready);
+        let unparker = Arc::new(Mutex::new(None));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (slot, parked) = (unparker.clone(), events.clone());
+        _ = scheduler.submit_co(
+            move |_, ()| {
+                *slot.lock().unwrap() = Some(Scheduler::unparker());
+                parked.lock().unwrap().push("park");
+                Scheduler::park();
+                parked.lock().unwrap().push("unparked");
+            },
+            None,
+            None,
+        )?;
+        let unparking = events.clone();
+        _ = scheduler.submit_co(
+            move |suspender, ()| {
+                unparker
+                    .lock()
+                    .unwrap()
+                    .take()
+                    .expect("not parked")
+                    .unpark();
+                unparking.lock().unwrap().push("unpark");
+                suspender.suspend();
+            },
+            None,
+            None,
+        )?;
+        let other = events.clone();
+        _ = scheduler.submit_co(move |_, ()| other.lock().unwrap().push("3"), None, None)?;
+        scheduler.try_schedule()?;
+        assert_eq!(expected.to_vec(), *events.lock().unwrap());
+    }
+    Ok(())
+}
@@ -48,11 +48,13 @@
 //! see `https://github.com/acl-dev/open-coroutine`
 
 use once_cell::sync::OnceCell;
-use open_coroutine_core::co_pool::task::UserTaskFunc;
+use open_coroutine_core::co_pool::task::{UserTaskFunc, UserTaskResultDrop};
+use open_coroutine_core::common::join_error::{JoinError, JoinErrorKind};
 use open_coroutine_core::config::Config;
 use open_coroutine_core::net::join::JoinHandle;
+use open_coroutine_core::net::listener::ListenerTable;
 use open_coroutine_core::net::{EventLoops, UserFunc};
-use open_coroutine_core::scheduler::SchedulableCoroutine;
+use open_coroutine_core::scheduler::{SchedulableCoroutine, Scheduler, Unparker};
 use std::ffi::{c_int, c_longlong, c_uint};
 use std::time::Duration;
 
@@ -80,6 +82,15 @@ pub extern "C" fn open_coroutine_init(config: Config) -> c_int {
     0
 }
 
+/// Add a listener to all event-loops, it should be called before `open_coroutine_init`.
+#[no_mangle]
+pub extern "C" fn open_coroutine_add_listener(listener: ListenerTable) -> c_int {
+    if EventLoops::add_raw_listener(Box::leak(Box::new(listener))).is_ok() {
+        return 0;
+    }
+    -1
+}
+
 /// Stop the framework.
 #[no_mangle]
 pub extern "C" fn open_coroutine_stop(secs: c_uint) -> c_int {
@@ -89,28 +100,86 @@ pub extern "C" fn open_coroutine_stop(secs: c_uint) -> c_int {
     -1
 }
 
+/// The result returned by `UserTaskFunc`, it will be released if nobody joins.
+#[repr(C)]
+#[derive(Debug)]
+struct UserTaskResult(usize, UserTaskResultDrop);
+
+impl UserTaskResult {
+    fn into_raw(self) -> usize {
+        let ptr = self.0;
+        std::mem::forget(self);
+        ptr
+    }
+}
+
+impl Drop for UserTaskResult {
+    fn drop(&mut self) {
+        (self.1)(self.0);
+    }
+}
+
 ///创建任务
 #[no_mangle]
-pub extern "C" fn task_crate(f: UserTaskFunc, param: usize, priority: c_longlong) -> JoinHandle {
+pub extern "C" fn task_crate(
+    f: UserTaskFunc,
+    param: usize,
+    priority: c_longlong,
+    drop_result: UserTaskResultDrop,
+) -> JoinHandle {
     EventLoops::submit_task(
         None,
-        move |p| Some(f(p.unwrap_or(0))),
+        move |p| UserTaskResult(f(p.unwrap_or(0)), drop_result),
         Some(param),
         Some(priority),
     )
+    .unwrap_or_else(|_| JoinHandle::rejected())
+}
+
+fn into_raw_result(result: Box<dyn std::any::Any + Send>) -> c_longlong {
+    result.downcast::<UserTaskResult>().map_or(-1, |r| {
+        c_longlong::try_from(r.into_raw()).expect("overflow")
+    })
+}
+
+///任务失败时返回的错误码，用户任务的panic已在`open-coroutine`中捕获，因此这里的panic返回-1
+fn join_error_code(error: &JoinError) -> c_longlong {
+    match error.kind() {
+        JoinErrorKind::Panic => -1,
+        JoinErrorKind::Cancelled => -2,
+        JoinErrorKind::TimedOut => -3,
+        JoinErrorKind::StackOverflow => -4,
+        JoinErrorKind::InvalidMemory => -5,
+        JoinErrorKind::DeadlineMissed => -6,
+        JoinErrorKind::Rejected => -7,
+        JoinErrorKind::Expired => -8,
+    }
+}
+
+///取消任务
+#[no_mangle]
+pub extern "C" fn task_cancel(handle: &JoinHandle) -> c_int {
+    match handle.cancel() {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+///分离任务，任务完成时丢弃结果
+#[no_mangle]
+pub extern "C" fn task_detach(handle: &JoinHandle) -> c_int {
+    match handle.detach() {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
 }
 
 ///等待任务完成
 #[no_mangle]
 pub extern "C" fn task_join(handle: &JoinHandle) -> c_longlong {
     match handle.join() {
-        Ok(ptr) => match ptr {
-            Ok(ptr) => match ptr {
-                Some(ptr) => c_longlong::try_from(ptr).expect("overflow"),
-                None => 0,
-            },
-            Err(_) => -1,
-        },
+        Ok(Ok(result)) => into_raw_result(result),
+        Ok(Err(e)) => join_error_code(&e),
         Err(_) => -1,
     }
 }
@@ -119,17 +188,60 @@ pub extern "C" fn task_join(handle: &JoinHandle) -> c_longlong {
 #[no_mangle]
 pub extern "C" fn task_timeout_join(handle: &JoinHandle, ns_time: u64) -> c_longlong {
     match handle.timeout_join(Duration::from_nanos(ns_time)) {
-        Ok(ptr) => match ptr {
-            Ok(ptr) => match ptr {
-                Some(ptr) => c_longlong::try_from(ptr).expect("overflow"),
-                None => 0,
-            },
-            Err(_) => -1,
-        },
+        Ok(Ok(result)) => into_raw_result(result),
+        Ok(Err(e)) => join_error_code(&e),
+        Err(_) => -1,
+    }
+}
+
+///释放任务句柄，句柄中的任务名由dylib分配，必须由dylib释放
+#[no_mangle]
+pub extern "C" fn task_handle_drop(handle: JoinHandle) {
+    drop(handle);
+}
+
+///挂起当前协程，直到被唤醒或超时，`u64::MAX`表示不超时；
+///协程被取消时返回-1，栈不能跨越dylib展开，由调用方继续展开
+#[no_mangle]
+pub extern "C" fn coroutine_park(ns_time: u64) -> c_int {
+    let parked = std::panic::catch_unwind(|| {
+        if u64::MAX == ns_time {
+            Scheduler::park();
+        } else {
+            Scheduler::park_timeout(Duration::from_nanos(ns_time));
+        }
+    });
+    match parked {
+        Ok(()) => 0,
         Err(_) => -1,
     }
 }
 
+///获取当前协程的唤醒器，返回不透明句柄，用完需调用`coroutine_unparker_drop`释放
+#[no_mangle]
+pub extern "C" fn coroutine_unparker() -> usize {
+    Box::into_raw(Box::new(Scheduler::unparker())) as usize
+}
+
+///复制唤醒器句柄
+#[no_mangle]
+pub extern "C" fn coroutine_unparker_clone(handle: usize) -> usize {
+    let unparker = unsafe { &*(handle as *const Unparker) };
+    Box::into_raw(Box::new(unparker.clone())) as usize
+}
+
+///唤醒协程
+#[no_mangle]
+pub extern "C" fn coroutine_unpark(handle: usize) {
+    unsafe { &*(handle as *const Unparker) }.unpark();
+}
+
+///释放唤醒器句柄
+#[no_mangle]
+pub extern "C" fn coroutine_unparker_drop(handle: usize) {
+    drop(unsafe { Box::from_raw(handle as *mut Unparker) });
+}
+
 ///如果当前协程栈不够，切换到新栈上执行
 #[no_mangle]
 pub extern "C" fn maybe_grow_stack(
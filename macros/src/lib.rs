@@ -62,6 +62,10 @@ pub fn main(args: TokenStream, func: TokenStream) -> TokenStream {
     let mut min_memory_count = usize::MAX;
     let mut memory_keep_alive_time = u64::MAX;
     let mut hook = true;
+    let mut stack_canary = false;
+    let mut aging_threshold = u64::MAX;
+    let mut task_capacity = usize::MAX;
+    let mut result_ttl = u64::MAX;
     if !args.is_empty() {
         let tea_parser = syn::meta::parser(|meta| {
             if meta.path.is_ident("event_loop_size") {
@@ -80,6 +84,14 @@ pub fn main(args: TokenStream, func: TokenStream) -> TokenStream {
                 memory_keep_alive_time = meta.value()?.parse::<LitInt>()?.base10_parse()?;
             } else if meta.path.is_ident("hook") {
                 hook = meta.value()?.parse::<LitBool>()?.value();
+            } else if meta.path.is_ident("stack_canary") {
+                stack_canary = meta.value()?.parse::<LitBool>()?.value();
+            } else if meta.path.is_ident("aging_threshold") {
+                aging_threshold = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+            } else if meta.path.is_ident("task_capacity") {
+                task_capacity = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+            } else if meta.path.is_ident("result_ttl") {
+                result_ttl = meta.value()?.parse::<LitInt>()?.base10_parse()?;
             }
             Ok(())
         });
@@ -124,6 +136,18 @@ pub fn main(args: TokenStream, func: TokenStream) -> TokenStream {
             if #hook != true {
                 open_coroutine_config.set_hook(#hook);
             }
+            if #stack_canary {
+                open_coroutine_config.set_stack_canary(#stack_canary);
+            }
+            if #aging_threshold != u64::MAX {
+                open_coroutine_config.set_aging_threshold(#aging_threshold);
+            }
+            if #task_capacity != usize::MAX {
+                open_coroutine_config.set_task_capacity(#task_capacity);
+            }
+            if #result_ttl != u64::MAX {
+                open_coroutine_config.set_result_ttl(#result_ttl);
+            }
             open_coroutine::init(open_coroutine_config);
             let _open_coroutine_result = #func_block;
             open_coroutine::shutdown();
@@ -0,0 +1,21 @@
+use open_coroutine::task;
+
+open_coroutine_core::coroutine_local! {
+    static REQUEST_ID: String = String::from("none");
+}
+
+#[open_coroutine::main(event_loop_size = 1, max_size = 1)]
+pub fn main() {
+    REQUEST_ID.set(String::from("main"));
+    // the locals are not inherited across the hook dylib, pass the value explicitly
+    let (inherited, passed) = task!(
+        |request_id: String| (REQUEST_ID.with(String::clone), request_id),
+        REQUEST_ID.with(String::clone),
+    )
+    .join()
+    .expect("join failed")
+    .expect("no result");
+    assert_eq!("none", inherited);
+    assert_eq!("main", passed);
+    println!("the task got request id {passed}");
+}
@@ -0,0 +1,20 @@
+use open_coroutine::{task, Unparker};
+use std::sync::mpsc::channel;
+
+#[open_coroutine::main(event_loop_size = 1, max_size = 1)]
+pub fn main() {
+    let (tx, rx) = channel();
+    let handle = task!(
+        |tx: std::sync::mpsc::Sender<Unparker>| {
+            tx.send(Unparker::current()).expect("send failed");
+            open_coroutine::park();
+            1
+        },
+        tx,
+    );
+    let unparker = rx.recv().expect("recv failed");
+    unparker.clone().unpark();
+    drop(unparker);
+    assert_eq!(Some(1), handle.join().expect("join failed"));
+    println!("the parked task is unparked");
+}
@@ -0,0 +1,40 @@
+use open_coroutine::JoinErrorKind;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+#[open_coroutine::main(event_loop_size = 1, max_size = 2)]
+pub fn main() -> std::io::Result<()> {
+    let numbers = [1, 2, 3, 4];
+    let mut sum = 0;
+    let doubled = open_coroutine::scope(|s| {
+        // the tasks borrow from the stack
+        s.spawn(|| sum = numbers.iter().sum());
+        let doubled = s.spawn(|| numbers.iter().map(|n| n * 2).collect::<Vec<_>>());
+        doubled.join()
+    })??;
+    assert_eq!(10, sum);
+    assert_eq!(vec![2, 4, 6, 8], doubled);
+    println!("scope finished with sum {sum}");
+
+    let completed = AtomicBool::new(false);
+    let error = open_coroutine::scope(|s| {
+        s.set_cancel_on_error(true);
+        _ = s.spawn(|| panic!("test panic, just ignore it"));
+        _ = s.spawn(|| {
+            for _ in 0..100 {
+                open_coroutine::park_timeout(Duration::from_millis(10));
+            }
+            completed.store(true, Ordering::Release);
+        });
+    })
+    .expect_err("the panic should be propagated");
+    // the sibling is cancelled before it completes
+    assert!(!completed.load(Ordering::Acquire));
+    let error = error
+        .into_inner()
+        .and_then(|e| e.downcast::<open_coroutine::JoinError>().ok())
+        .expect("unexpected error");
+    assert_eq!(JoinErrorKind::Panic, error.kind());
+    println!("scope failed with {error}");
+    Ok(())
+}
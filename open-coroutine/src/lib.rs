@@ -47,25 +47,36 @@
 )]
 //! see `https://github.com/acl-dev/open-coroutine`
 
-use open_coroutine_core::co_pool::task::UserTaskFunc;
+use open_coroutine_core::co_pool::task::{UserTaskFunc, UserTaskResultDrop};
+pub use open_coroutine_core::co_pool::RejectPolicy;
 use open_coroutine_core::common::constants::SLICE;
+pub use open_coroutine_core::common::join_error::{JoinError, JoinErrorKind};
 pub use open_coroutine_core::common::ordered_work_steal::DEFAULT_PRECEDENCE;
 pub use open_coroutine_core::config::Config;
+pub use open_coroutine_core::coroutine::listener::Listener;
+use open_coroutine_core::net::listener::ListenerTable;
 use open_coroutine_core::net::UserFunc;
 pub use open_coroutine_macros::*;
+pub use scope::{scope, Scope, ScopedJoinHandle};
 use std::cmp::Ordering;
 use std::ffi::{c_int, c_longlong, c_uint, c_void};
 use std::io::{Error, ErrorKind};
 use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
 use std::net::{TcpStream, ToSocketAddrs};
 use std::ops::Deref;
 use std::time::Duration;
 
+/// Structured concurrency for tasks.
+mod scope;
+
 extern "C" {
     fn open_coroutine_init(config: Config) -> c_int;
 
     fn open_coroutine_stop(secs: c_uint) -> c_int;
 
+    fn open_coroutine_add_listener(listener: ListenerTable) -> c_int;
+
     fn maybe_grow_stack(
         red_zone: usize,
         stack_size: usize,
@@ -80,14 +91,31 @@ extern "C" {
         f: UserTaskFunc,
         param: usize,
         priority: c_longlong,
+        drop_result: UserTaskResultDrop,
     ) -> open_coroutine_core::net::join::JoinHandle;
 
+    fn task_cancel(handle: &open_coroutine_core::net::join::JoinHandle) -> c_int;
+
+    fn task_detach(handle: &open_coroutine_core::net::join::JoinHandle) -> c_int;
+
     fn task_join(handle: &open_coroutine_core::net::join::JoinHandle) -> c_longlong;
 
     fn task_timeout_join(
         handle: &open_coroutine_core::net::join::JoinHandle,
         ns_time: u64,
     ) -> c_longlong;
+
+    fn task_handle_drop(handle: open_coroutine_core::net::join::JoinHandle);
+
+    fn coroutine_park(ns_time: u64) -> c_int;
+
+    fn coroutine_unparker() -> usize;
+
+    fn coroutine_unparker_clone(handle: usize) -> usize;
+
+    fn coroutine_unpark(handle: usize);
+
+    fn coroutine_unparker_drop(handle: usize);
 }
 
 /// Init the open-coroutine.
@@ -101,12 +129,26 @@ pub fn init(config: Config) {
     open_coroutine_core::common::ci::init();
 }
 
+/// Add a listener to all event-loops, it should be called before [`init`].
+pub fn add_listener(
+    listener: impl Listener<(), Option<usize>> + Send + Sync + 'static,
+) -> std::io::Result<()> {
+    if unsafe { open_coroutine_add_listener(ListenerTable::new(listener)) } < 0 {
+        return Err(Error::new(ErrorKind::Other, "add listener failed"));
+    }
+    Ok(())
+}
+
 /// Shutdown the open-coroutine.
 pub fn shutdown() {
     unsafe { _ = open_coroutine_stop(30) };
 }
 
 /// Create a task.
+///
+/// The task is scheduled by the copy of `open-coroutine-core` inside the hook dylib, so the
+/// coroutine locals declared by the application are neither inherited by the task nor
+/// visible in it, pass the values as the task param instead.
 #[macro_export]
 macro_rules! task {
     ( $f: expr , $param:expr , $priority: expr $(,)? ) => {
@@ -117,7 +159,7 @@ macro_rules! task {
     };
 }
 
-/// Create a task.
+/// Create a task, see [`task!`].
 pub fn crate_task<P: 'static, R: 'static, F: FnOnce(P) -> R>(
     f: F,
     param: P,
@@ -127,59 +169,93 @@ pub fn crate_task<P: 'static, R: 'static, F: FnOnce(P) -> R>(
         unsafe {
             let ptr = &mut *((input as *mut c_void).cast::<(F, P)>());
             let data = std::ptr::read_unaligned(ptr);
-            let result: &'static mut std::io::Result<R> = Box::leak(Box::new(
-                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| (data.0)(data.1)))
-                    .map_err(|e| {
-                        Error::new(
-                            ErrorKind::Other,
-                            e.downcast_ref::<&'static str>()
-                                .map_or("task failed without message", |msg| *msg),
-                        )
-                    }),
-            ));
+            let result: &'static mut Result<R, JoinError> =
+                Box::leak(Box::new(JoinError::catch_unwind(|| (data.0)(data.1))));
             std::ptr::from_mut(result).cast::<c_void>() as usize
         }
     }
+    extern "C" fn task_result_drop<R: 'static>(ptr: usize) {
+        unsafe { drop(Box::from_raw(ptr as *mut Result<R, JoinError>)) };
+    }
     let inner = Box::leak(Box::new((f, param)));
     unsafe {
         task_crate(
             task_main::<P, R, F>,
             std::ptr::from_mut(inner).cast::<c_void>() as usize,
             priority,
+            task_result_drop::<R>,
         )
         .into()
     }
 }
 
-#[allow(missing_docs)]
+/// The handle of a task created by [`task!`].
+///
+/// The inner handle is allocated by the hook dylib, so it's released by the dylib too.
 #[repr(C)]
 #[derive(Debug)]
-pub struct JoinHandle<R>(open_coroutine_core::net::join::JoinHandle, PhantomData<R>);
+pub struct JoinHandle<R>(
+    ManuallyDrop<open_coroutine_core::net::join::JoinHandle>,
+    PhantomData<R>,
+);
 
 #[allow(missing_docs)]
 impl<R> JoinHandle<R> {
+    pub fn cancel(&self) -> std::io::Result<()> {
+        if unsafe { task_cancel(self) } < 0 {
+            return Err(Error::new(ErrorKind::Other, "cancel failed"));
+        }
+        Ok(())
+    }
+
+    /// Detach the task, its result will be dropped when it completes instead of being kept
+    /// until it's joined.
+    pub fn detach(self) -> std::io::Result<()> {
+        if unsafe { task_detach(&self) } < 0 {
+            return Err(Error::new(ErrorKind::Other, "detach failed"));
+        }
+        Ok(())
+    }
+
+    /// If the task failed, the error wraps a [`JoinError`], use [`Error::into_inner`]
+    /// to downcast it, then the panic payload can be passed to [`std::panic::resume_unwind`].
     pub fn timeout_join(&self, dur: Duration) -> std::io::Result<Option<R>> {
         unsafe {
             let ptr = task_timeout_join(self, dur.as_nanos().try_into().expect("overflow"));
-            match ptr.cmp(&0) {
-                Ordering::Less => Err(Error::new(ErrorKind::Other, "timeout join failed")),
-                Ordering::Equal => Ok(None),
-                Ordering::Greater => Ok(Some((*Box::from_raw(ptr as *mut std::io::Result<R>))?)),
-            }
+            Self::into_result(ptr, "timeout join failed")
         }
     }
 
+    /// see `timeout_join`.
     pub fn join(self) -> std::io::Result<Option<R>> {
         unsafe {
             let ptr = task_join(&self);
-            match ptr.cmp(&0) {
-                Ordering::Less => Err(Error::new(ErrorKind::Other, "join failed")),
-                Ordering::Equal => Ok(None),
-                Ordering::Greater => Ok(Some((*Box::from_raw(ptr as *mut std::io::Result<R>))?)),
-            }
+            Self::into_result(ptr, "join failed")
         }
     }
 
+    unsafe fn into_result(ptr: c_longlong, msg: &str) -> std::io::Result<Option<R>> {
+        let kind = match ptr.cmp(&0) {
+            Ordering::Less => match ptr {
+                -2 => JoinErrorKind::Cancelled,
+                -3 => JoinErrorKind::TimedOut,
+                -4 => JoinErrorKind::StackOverflow,
+                -5 => JoinErrorKind::InvalidMemory,
+                -6 => JoinErrorKind::DeadlineMissed,
+                -7 => JoinErrorKind::Rejected,
+                -8 => JoinErrorKind::Expired,
+                _ => return Err(Error::new(ErrorKind::Other, msg)),
+            },
+            Ordering::Equal => return Ok(None),
+            Ordering::Greater => {
+                return (*Box::from_raw(ptr as *mut Result<R, JoinError>))
+                    .map(Some)
+                    .map_err(Error::from)
+            }
+        };
+        Err(Error::from(JoinError::new(kind)))
+    }
+
     pub fn any_timeout_join(dur: Duration, slice: &[Self]) -> std::io::Result<Option<R>> {
         if slice.is_empty() {
             return Ok(None);
@@ -189,10 +265,11 @@ impl<R> JoinHandle<R> {
             for handle in slice {
                 let left_time = timeout_time.saturating_sub(open_coroutine_core::common::now());
                 if 0 == left_time {
-                    return Err(Error::new(ErrorKind::Other, "timeout join failed"));
+                    return Err(Error::from(JoinError::new(JoinErrorKind::TimedOut)));
                 }
-                if let Ok(x) = handle.timeout_join(Duration::from_nanos(left_time).min(SLICE)) {
-                    return Ok(x);
+                match handle.timeout_join(Duration::from_nanos(left_time).min(SLICE)) {
+                    Err(e) if ErrorKind::TimedOut == e.kind() => {}
+                    r => return r,
                 }
             }
         }
@@ -203,15 +280,16 @@ impl<R> JoinHandle<R> {
     }
 }
 
+/// The `val` must be created by the hook dylib, such as the handle returned by `task_crate`.
 impl<R> From<open_coroutine_core::net::join::JoinHandle> for JoinHandle<R> {
     fn from(val: open_coroutine_core::net::join::JoinHandle) -> Self {
-        Self(val, PhantomData)
+        Self(ManuallyDrop::new(val), PhantomData)
     }
 }
 
-impl<R> From<JoinHandle<R>> for open_coroutine_core::net::join::JoinHandle {
-    fn from(val: JoinHandle<R>) -> Self {
-        val.0
+impl<R> Drop for JoinHandle<R> {
+    fn drop(&mut self) {
+        unsafe { task_handle_drop(ManuallyDrop::take(&mut self.0)) };
     }
 }
 
@@ -239,6 +317,58 @@ macro_rules! any_join {
     }
 }
 
+/// Park the current coroutine until it's unparked by its [`Unparker`].
+///
+/// If we are not in a coroutine, the current thread will be parked.
+/// If the task is cancelled while parked, it will unwind from here.
+pub fn park() {
+    if unsafe { coroutine_park(u64::MAX) } < 0 {
+        std::panic::resume_unwind(Box::new("coroutine cancelled"));
+    }
+}
+
+/// Park the current coroutine until it's unparked or the `dur` elapsed.
+///
+/// If we are not in a coroutine, the current thread will be parked.
+/// If the task is cancelled while parked, it will unwind from here.
+pub fn park_timeout(dur: Duration) {
+    if unsafe { coroutine_park(dur.as_nanos().try_into().unwrap_or(u64::MAX - 1)) } < 0 {
+        std::panic::resume_unwind(Box::new("coroutine cancelled"));
+    }
+}
+
+/// A handle to wake up a parked coroutine, it can be sent to any thread.
+#[repr(C)]
+#[derive(Debug)]
+pub struct Unparker(usize);
+
+impl Unparker {
+    /// Returns the [`Unparker`] of the current coroutine, or the current thread if
+    /// we are not in a coroutine.
+    #[must_use]
+    pub fn current() -> Self {
+        Self(unsafe { coroutine_unparker() })
+    }
+
+    /// Move the parked coroutine back to the ready queue of its scheduler, if the
+    /// coroutine is not parked, its next [`park`] will return immediately.
+    pub fn unpark(&self) {
+        unsafe { coroutine_unpark(self.0) };
+    }
+}
+
+impl Clone for Unparker {
+    fn clone(&self) -> Self {
+        Self(unsafe { coroutine_unparker_clone(self.0) })
+    }
+}
+
+impl Drop for Unparker {
+    fn drop(&mut self) {
+        unsafe { coroutine_unparker_drop(self.0) };
+    }
+}
+
 /// Grows the call stack if necessary.
 #[macro_export]
 macro_rules! maybe_grow {
@@ -0,0 +1,200 @@
+use crate::{crate_task, JoinHandle, Unparker, DEFAULT_PRECEDENCE};
+use std::cell::{Cell, RefCell};
+use std::io::{Error, ErrorKind};
+use std::marker::PhantomData;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A scope to spawn tasks, see [`scope`].
+#[derive(Debug)]
+pub struct Scope<'scope, 'env: 'scope> {
+    //子任务的句柄，被join后置为None
+    children: RefCell<Vec<Option<JoinHandle<()>>>>,
+    cancel_on_error: Cell<bool>,
+    first_error: RefCell<Option<Error>>,
+    signal: Signal,
+    phantom_data: PhantomData<(&'scope mut &'scope (), &'env mut &'env ())>,
+}
+
+/// Wakes up the scope when its tasks end.
+#[derive(Debug)]
+struct Signal {
+    //已结束的子任务的下标
+    finished: Mutex<Vec<usize>>,
+    unparker: Unparker,
+}
+
+impl Signal {
+    fn finished(&self) -> MutexGuard<'_, Vec<usize>> {
+        self.finished.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn notify(&self, index: usize) {
+        self.finished().push(index);
+        self.unparker.unpark();
+    }
+}
+
+/// Moved into the task, it notifies the scope when dropped, even if the task panicked,
+/// was cancelled, or was dropped without running.
+#[derive(Debug)]
+struct SignalGuard<'scope>(&'scope Signal, usize);
+
+impl Drop for SignalGuard<'_> {
+    fn drop(&mut self) {
+        self.0.notify(self.1);
+    }
+}
+
+/// An owned permission to join on a task spawned in a [`Scope`].
+#[derive(Debug)]
+pub struct ScopedJoinHandle<'scope, R> {
+    children: &'scope RefCell<Vec<Option<JoinHandle<()>>>>,
+    index: usize,
+    result: Arc<Mutex<Option<R>>>,
+}
+
+/// Create a scope to spawn tasks, the tasks can borrow the non-`'static` data outside the
+/// scope, because all tasks spawned in the scope are joined before this function returns.
+///
+/// If a task failed and was not joined by its [`ScopedJoinHandle`], the first failure is
+/// returned, the error wraps a [`JoinError`](crate::JoinError).
+///
+/// # Examples
+///
+/// ```no_run
+/// let mut a = vec![1, 2, 3];
+/// let mut x = 0;
+/// open_coroutine::scope(|s| {
+///     s.spawn(|| println!("hello from the first task, a is {a:?}"));
+///     s.spawn(|| {
+///         println!("hello from the second task");
+///         x += a[0] + a[2];
+///     });
+/// })
+/// .expect("a task failed");
+/// a.push(4);
+/// assert_eq!(4, x);
+/// ```
+pub fn scope<'env, T>(
+    f: impl for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+) -> std::io::Result<T> {
+    let scope = Scope {
+        children: RefCell::new(Vec::new()),
+        cancel_on_error: Cell::new(false),
+        first_error: RefCell::new(None),
+        signal: Signal {
+            finished: Mutex::new(Vec::new()),
+            unparker: Unparker::current(),
+        },
+        phantom_data: PhantomData,
+    };
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+    //即使f panic，也要等待所有子任务结束
+    scope.wait();
+    match result {
+        Ok(result) => scope.first_error.take().map_or(Ok(result), Err),
+        Err(payload) => std::panic::resume_unwind(payload),
+    }
+}
+
+impl<'scope> Scope<'scope, '_> {
+    /// Spawn a task in this scope, it will be joined when the scope ends.
+    pub fn spawn<R: Send + 'scope, F: FnOnce() -> R + Send + 'scope>(
+        &'scope self,
+        f: F,
+    ) -> ScopedJoinHandle<'scope, R> {
+        let result = Arc::new(Mutex::new(None));
+        let slot = result.clone();
+        let index = self.children.borrow().len();
+        let guard = SignalGuard(&self.signal, index);
+        //结果通过slot传递，任务本身只返回()，所以scope可以统一等待所有子任务
+        let handle = crate_task(
+            move |()| {
+                let _guard = guard;
+                let value = f();
+                *slot.lock().unwrap_or_else(PoisonError::into_inner) = Some(value);
+            },
+            (),
+            DEFAULT_PRECEDENCE,
+        );
+        self.children.borrow_mut().push(Some(handle));
+        ScopedJoinHandle {
+            children: &self.children,
+            index,
+            result,
+        }
+    }
+
+    /// Cancel the other tasks in this scope when a task failed, it's disabled by default.
+    pub fn set_cancel_on_error(&self, cancel_on_error: bool) {
+        self.cancel_on_error.set(cancel_on_error);
+    }
+
+    fn wait(&self) {
+        let mut cancelled = None;
+        while self.children.borrow().iter().any(Option::is_some) {
+            let finished = std::mem::take(&mut *self.signal.finished());
+            if finished.is_empty() {
+                //子任务可能借用了栈上的数据，当前任务被取消时也要等它们结束
+                if let Err(payload) = std::panic::catch_unwind(crate::park) {
+                    if cancelled.is_none() {
+                        self.cancel_all();
+                    }
+                    cancelled = Some(payload);
+                }
+                continue;
+            }
+            for index in finished {
+                //已被ScopedJoinHandle join过
+                let Some(handle) = self.children.borrow_mut()[index].take() else {
+                    continue;
+                };
+                if let Err(e) = handle.join() {
+                    self.failed(e);
+                }
+            }
+        }
+        if let Some(payload) = cancelled {
+            std::panic::resume_unwind(payload);
+        }
+    }
+
+    fn failed(&self, error: Error) {
+        let mut first_error = self.first_error.borrow_mut();
+        if first_error.is_some() {
+            return;
+        }
+        *first_error = Some(error);
+        if self.cancel_on_error.get() {
+            self.cancel_all();
+        }
+    }
+
+    fn cancel_all(&self) {
+        for (index, handle) in self.children.borrow().iter().enumerate() {
+            if let Some(handle) = handle {
+                _ = handle.cancel();
+                //未开始执行就被取消的任务不会被drop，直接去join
+                self.signal.notify(index);
+            }
+        }
+    }
+}
+
+impl<R> ScopedJoinHandle<'_, R> {
+    /// Wait for the task to finish, then the scope won't check it again.
+    ///
+    /// If the task failed, the error wraps a [`JoinError`](crate::JoinError).
+    pub fn join(self) -> std::io::Result<R> {
+        let handle = self.children.borrow_mut()[self.index]
+            .take()
+            .ok_or_else(|| Error::new(ErrorKind::Other, "The task has been joined !"))?;
+        _ = handle.join()?;
+        self.result
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .ok_or_else(|| Error::new(ErrorKind::Other, "The task has no result !"))
+    }
+}
@@ -0,0 +1,6 @@
+include!("../examples/coroutine_local.rs");
+
+#[test]
+fn coroutine_local() {
+    main();
+}
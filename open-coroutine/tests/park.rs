@@ -0,0 +1,6 @@
+include!("../examples/park.rs");
+
+#[test]
+fn park() {
+    main();
+}
@@ -0,0 +1,6 @@
+include!("../examples/scope.rs");
+
+#[test]
+fn scope() -> std::io::Result<()> {
+    main()
+}